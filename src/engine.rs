@@ -1,4 +1,12 @@
 pub mod engine;
 pub mod general_traits;
 pub mod scene;
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod entity_id;
+pub mod world;
+pub mod components;
+pub mod systems;
+pub mod hierarchy;
+pub mod camera_controller;
+pub mod clock;
+pub mod scene_description;
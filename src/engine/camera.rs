@@ -1,7 +1,6 @@
-use crate::{physics::physics_traits::{Transform, Movable}, engine::general_traits::Entity};
+use crate::{physics::physics_traits::{HasTransform, Transform, Movable}, engine::general_traits::Entity};
 
 use glam::{Mat4, Vec3};
-use nanoid::nanoid;
 use vulkano::buffer::view;
 
 use super::general_traits::{TickAction};
@@ -11,8 +10,7 @@ pub struct Camera {
     transform: Transform,
     projection_matrix: Mat4,
     view_matrix: Mat4,
-    pub projection_view_matrix: Mat4,
-    id: String
+    pub projection_view_matrix: Mat4
 }
 
 impl Camera {
@@ -33,25 +31,51 @@ impl Camera {
             projection_matrix: projection_matrix,
             view_matrix: view_matrix,
             projection_view_matrix,
-            id: nanoid!()
         }
     }
 
-    pub fn recalculate_projection_view_matrix(&mut self) -> () {
-        let translation_matrix = Mat4::from_translation(self.transform.translation);
-        let orientation_matrix = Mat4::from_axis_angle(self.transform.rotation.xyz().normalize(), self.transform.rotation.to_axis_angle().1);
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    // e.g. driven by a camera_controller::CameraController
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.recalculate_projection_view_matrix();
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        self.projection_matrix
+    }
+
+    // keeps the vertical field of view (or height for orthographic projections) and fits the horizontal one, e.g. after the window was resized
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        if !aspect_ratio.is_finite() || aspect_ratio <= 0. {
+            return;
+        }
+        self.projection_matrix.x_axis.x = self.projection_matrix.x_axis.x.signum() * self.projection_matrix.y_axis.y.abs() / aspect_ratio;
+        self.projection_view_matrix = self.projection_matrix * self.view_matrix;
+    }
 
-        self.view_matrix = (translation_matrix * orientation_matrix).inverse();
+    pub fn recalculate_projection_view_matrix(&mut self) -> () {
+        // the camera's scale doesn't scale the world
+        self.view_matrix = Mat4::from_rotation_translation(self.transform.rotation, self.transform.translation).inverse();
         self.projection_view_matrix = self.projection_matrix * self.view_matrix;
     }
 }
 
 impl Entity for Camera {
-    fn tick(self: &mut Camera) -> Option<TickAction> {
+    fn tick(self: &mut Camera, _delta_time: f32) -> Option<TickAction> {
         None
     }
 }
 
+impl HasTransform for Camera {
+    fn get_transform(&self) -> Transform {
+        self.transform
+    }
+}
+
 impl Movable for Camera {
     fn update_position(&mut self) -> () {
         self.move_y(0.005);
@@ -81,3 +105,31 @@ impl Movable for Camera {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratio_changes_keep_the_vertical_field_of_view() {
+        let mut camera = Camera::new(Transform::default(), Mat4::perspective_lh(1., 16. / 9., 1., 100.));
+        camera.set_aspect_ratio(4. / 3.);
+        assert!(camera.projection_view_matrix.abs_diff_eq(Mat4::perspective_lh(1., 4. / 3., 1., 100.), 1e-6));
+        // minimized windows have no aspect ratio
+        camera.set_aspect_ratio(f32::NAN);
+        camera.set_aspect_ratio(0.);
+        assert!(camera.projection_view_matrix.abs_diff_eq(Mat4::perspective_lh(1., 4. / 3., 1., 100.), 1e-6));
+    }
+
+    #[test]
+    fn moved_cameras_look_from_their_new_transform() {
+        let projection_matrix = Mat4::perspective_lh(1., 1., 1., 100.);
+        let mut camera = Camera::new(Transform::default(), projection_matrix);
+        let transform = Transform::from_translation(Vec3::new(1., 2., -5.)).looking_at(Vec3::ZERO, Vec3::Y);
+        camera.set_transform(transform);
+        assert_eq!(camera.transform(), transform);
+        assert!(camera.projection_view_matrix.abs_diff_eq(Camera::new(transform, projection_matrix).projection_view_matrix, 1e-6));
+        // the looked at point ends up in the middle of the image
+        let clip_position = camera.projection_view_matrix * Vec3::ZERO.extend(1.);
+        assert!((clip_position.truncate() / clip_position.w).truncate().abs_diff_eq(glam::Vec2::ZERO, 1e-5));
+    }
+}
@@ -0,0 +1,477 @@
+// camera controllers turn keyboard and mouse input into camera transforms, see Engine::set_camera_controller.
+// all of them follow their input with a delay (see smoothing_factor), so the camera doesn't jerk around with every mouse event
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Quat, Vec2, Vec3};
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use crate::physics::physics_traits::Transform;
+
+// scroll wheels reporting pixels (e.g. touchpads) are converted to lines with this
+const PIXELS_PER_SCROLL_LINE: f32 = 20.;
+// looking straight up or down would make yaw meaningless
+const MAX_FLY_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+// keyboard and mouse state collected from winit events between two frames
+#[derive(Debug, Default)]
+pub struct CameraInput {
+    pressed_keys: HashSet<VirtualKeyCode>,
+    pressed_mouse_buttons: HashSet<MouseButton>,
+    // both accumulated since the last end_frame
+    mouse_delta: Vec2,
+    scroll_delta: f32,
+}
+
+impl CameraInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. } => self.set_key(*key, *state == ElementState::Pressed),
+            WindowEvent::MouseInput { state, button, .. } => self.set_mouse_button(*button, *state == ElementState::Pressed),
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(_, lines) => self.add_scroll(*lines),
+                MouseScrollDelta::PixelDelta(pixels) => self.add_scroll(pixels.y as f32 / PIXELS_PER_SCROLL_LINE),
+            },
+            // releases happening while the window isn't focused never arrive
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.pressed_mouse_buttons.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // raw mouse motion, unlike CursorMoved it keeps coming when the cursor reaches the border of the window
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            self.add_mouse_motion(Vec2::new(*x as f32, *y as f32));
+        }
+    }
+
+    pub fn set_key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        match pressed {
+            true => self.pressed_keys.insert(key),
+            false => self.pressed_keys.remove(&key),
+        };
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn set_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        match pressed {
+            true => self.pressed_mouse_buttons.insert(button),
+            false => self.pressed_mouse_buttons.remove(&button),
+        };
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_mouse_buttons.contains(&button)
+    }
+
+    // in pixels, x to the right and y down
+    pub fn add_mouse_motion(&mut self, delta: Vec2) {
+        self.mouse_delta += delta;
+    }
+
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    // in lines, positive is away from the user
+    pub fn add_scroll(&mut self, lines: f32) {
+        self.scroll_delta += lines;
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    // once the frame's input was used, held keys and buttons stay pressed
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.;
+    }
+
+    // -1, 0 or 1 depending on which of the two keys is held
+    fn axis(&self, negative: VirtualKeyCode, positive: VirtualKeyCode) -> f32 {
+        self.is_key_pressed(positive) as i32 as f32 - self.is_key_pressed(negative) as i32 as f32
+    }
+}
+
+pub trait CameraController {
+    // advances by delta_time seconds with the input collected since the last update, returns the new camera transform
+    fn update(&mut self, input: &CameraInput, delta_time: f32) -> Transform;
+    // continues from transform without smoothing, e.g. after the active scene changed
+    fn reset(&mut self, transform: Transform);
+}
+
+// fraction of the remaining way to the target to cover this frame, independent of the frame rate.
+// the higher the responsiveness the faster the target is reached, f32::INFINITY follows the input immediately
+pub fn smoothing_factor(responsiveness: f32, delta_time: f32) -> f32 {
+    1. - (-responsiveness * delta_time).exp()
+}
+
+// orientation of a camera that never rolls, pitch is positive when looking up
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LookAngles {
+    yaw: f32,
+    pitch: f32,
+}
+
+impl LookAngles {
+    fn from_rotation(rotation: Quat) -> Self {
+        let forward = rotation * Vec3::Z;
+        Self {
+            yaw: forward.x.atan2(forward.z),
+            pitch: forward.y.clamp(-1., 1.).asin(),
+        }
+    }
+
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(-self.pitch)
+    }
+
+    // moving the mouse right turns right, moving it down looks down
+    fn turn(&mut self, mouse_delta: Vec2, mouse_sensitivity: f32, max_pitch: f32) {
+        self.yaw += mouse_delta.x * mouse_sensitivity;
+        self.pitch = (self.pitch - mouse_delta.y * mouse_sensitivity).clamp(-max_pitch, max_pitch);
+    }
+
+    fn lerp(&self, other: &LookAngles, alpha: f32) -> Self {
+        Self {
+            yaw: self.yaw + (other.yaw - self.yaw) * alpha,
+            pitch: self.pitch + (other.pitch - self.pitch) * alpha,
+        }
+    }
+}
+
+// the tunables of the fly and first person controllers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FreeLookSettings {
+    // units per second
+    pub speed: f32,
+    // speed multiplier while left shift is held
+    pub sprint_multiplier: f32,
+    // radians per pixel of mouse movement
+    pub mouse_sensitivity: f32,
+    // how far up or down the camera can look, in radians
+    pub max_pitch: f32,
+    // see smoothing_factor
+    pub responsiveness: f32,
+}
+
+impl Default for FreeLookSettings {
+    fn default() -> Self {
+        Self {
+            speed: 3.,
+            sprint_multiplier: 3.,
+            mouse_sensitivity: 0.003,
+            max_pitch: MAX_FLY_PITCH,
+            responsiveness: 15.,
+        }
+    }
+}
+
+// where the camera is and where the input wants it to be
+#[derive(Debug, Clone, Copy)]
+struct FreeLook {
+    translation: Vec3,
+    look_angles: LookAngles,
+    target_translation: Vec3,
+    target_look_angles: LookAngles,
+}
+
+impl FreeLook {
+    fn new(transform: Transform) -> Self {
+        let look_angles = LookAngles::from_rotation(transform.rotation);
+        Self {
+            translation: transform.translation,
+            look_angles,
+            target_translation: transform.translation,
+            target_look_angles: look_angles,
+        }
+    }
+
+    // looks around while the right mouse button is held, moves with wasd (and q/e down/up unless horizontal)
+    fn update(&mut self, input: &CameraInput, delta_time: f32, settings: &FreeLookSettings, horizontal: bool) -> Transform {
+        if input.is_mouse_button_pressed(MouseButton::Right) {
+            self.target_look_angles.turn(input.mouse_delta(), settings.mouse_sensitivity, settings.max_pitch);
+        }
+        let move_rotation = match horizontal {
+            true => Quat::from_rotation_y(self.target_look_angles.yaw),
+            false => self.target_look_angles.rotation(),
+        };
+        let mut movement = move_rotation * Vec3::new(input.axis(VirtualKeyCode::A, VirtualKeyCode::D), 0., input.axis(VirtualKeyCode::S, VirtualKeyCode::W));
+        if !horizontal {
+            movement.y += input.axis(VirtualKeyCode::Q, VirtualKeyCode::E);
+        }
+        let speed = match input.is_key_pressed(VirtualKeyCode::LShift) {
+            true => settings.speed * settings.sprint_multiplier,
+            false => settings.speed,
+        };
+        self.target_translation += movement.normalize_or_zero() * speed * delta_time;
+
+        let smoothing_factor = smoothing_factor(settings.responsiveness, delta_time);
+        self.translation = self.translation.lerp(self.target_translation, smoothing_factor);
+        self.look_angles = self.look_angles.lerp(&self.target_look_angles, smoothing_factor);
+        Transform::new(self.translation, self.look_angles.rotation(), Vec3::ONE)
+    }
+}
+
+// flies in the direction the camera looks, with q/e to move straight down/up
+pub struct FlyController {
+    pub settings: FreeLookSettings,
+    free_look: FreeLook,
+}
+
+impl FlyController {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            settings: FreeLookSettings::default(),
+            free_look: FreeLook::new(transform),
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn update(&mut self, input: &CameraInput, delta_time: f32) -> Transform {
+        self.free_look.update(input, delta_time, &self.settings, false)
+    }
+
+    fn reset(&mut self, transform: Transform) {
+        self.free_look = FreeLook::new(transform);
+    }
+}
+
+// walks on the horizontal plane the camera started on, looking up or down is limited to settings.max_pitch
+pub struct FirstPersonController {
+    pub settings: FreeLookSettings,
+    free_look: FreeLook,
+}
+
+impl FirstPersonController {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            settings: FreeLookSettings { max_pitch: 85_f32.to_radians(), ..Default::default() },
+            free_look: FreeLook::new(transform),
+        }
+    }
+}
+
+impl CameraController for FirstPersonController {
+    fn update(&mut self, input: &CameraInput, delta_time: f32) -> Transform {
+        self.free_look.update(input, delta_time, &self.settings, true)
+    }
+
+    fn reset(&mut self, transform: Transform) {
+        self.free_look = FreeLook::new(transform);
+    }
+}
+
+// circles around a focus point: the left mouse button rotates, the right or middle one pans the focus and the scroll wheel zooms
+pub struct OrbitController {
+    // radians per pixel of mouse movement
+    pub mouse_sensitivity: f32,
+    // fraction of the distance zoomed per scroll line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // see smoothing_factor
+    pub responsiveness: f32,
+    focus: Vec3,
+    distance: f32,
+    look_angles: LookAngles,
+    target_focus: Vec3,
+    target_distance: f32,
+    target_look_angles: LookAngles,
+}
+
+impl OrbitController {
+    // starts at camera_translation looking at focus
+    pub fn new(focus: Vec3, camera_translation: Vec3) -> Self {
+        let transform = Transform::from_translation(camera_translation).looking_at(focus, Vec3::Y);
+        let distance = (focus - camera_translation).length();
+        let look_angles = LookAngles::from_rotation(transform.rotation);
+        Self {
+            mouse_sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 1000.,
+            responsiveness: 15.,
+            focus,
+            distance,
+            look_angles,
+            target_focus: focus,
+            target_distance: distance,
+            target_look_angles: look_angles,
+        }
+    }
+
+    // where the camera will look once the smoothing caught up
+    pub fn target_focus(&self) -> Vec3 {
+        self.target_focus
+    }
+
+    fn transform(focus: Vec3, distance: f32, look_angles: &LookAngles) -> Transform {
+        let rotation = look_angles.rotation();
+        Transform::new(focus - rotation * Vec3::Z * distance, rotation, Vec3::ONE)
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, input: &CameraInput, delta_time: f32) -> Transform {
+        let mouse_delta = input.mouse_delta();
+        if input.is_mouse_button_pressed(MouseButton::Left) {
+            self.target_look_angles.turn(mouse_delta, self.mouse_sensitivity, MAX_FLY_PITCH);
+        }
+        if input.is_mouse_button_pressed(MouseButton::Right) || input.is_mouse_button_pressed(MouseButton::Middle) {
+            // the focus follows the mouse, panning further away from it moves faster
+            let rotation = self.target_look_angles.rotation();
+            let pan = (rotation * Vec3::X * -mouse_delta.x + rotation * Vec3::Y * mouse_delta.y) * self.mouse_sensitivity * self.target_distance;
+            self.target_focus += pan;
+        }
+        self.target_distance = (self.target_distance * (1. - self.zoom_speed).powf(input.scroll_delta())).clamp(self.min_distance, self.max_distance);
+
+        let smoothing_factor = smoothing_factor(self.responsiveness, delta_time);
+        self.focus = self.focus.lerp(self.target_focus, smoothing_factor);
+        self.distance += (self.target_distance - self.distance) * smoothing_factor;
+        self.look_angles = self.look_angles.lerp(&self.target_look_angles, smoothing_factor);
+        Self::transform(self.focus, self.distance, &self.look_angles)
+    }
+
+    // keeps the distance, the new focus is that far in front of the camera
+    fn reset(&mut self, transform: Transform) {
+        self.look_angles = LookAngles::from_rotation(transform.rotation);
+        self.focus = transform.translation + self.look_angles.rotation() * Vec3::Z * self.target_distance;
+        self.distance = self.target_distance;
+        self.target_focus = self.focus;
+        self.target_look_angles = self.look_angles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 1. / 60.;
+
+    fn unsmoothed(mut settings: FreeLookSettings) -> FreeLookSettings {
+        settings.responsiveness = f32::INFINITY;
+        settings
+    }
+
+    #[test]
+    fn input_deltas_start_over_every_frame_but_held_keys_stay() {
+        let mut input = CameraInput::new();
+        input.set_key(VirtualKeyCode::W, true);
+        input.add_mouse_motion(Vec2::new(3., 4.));
+        input.add_mouse_motion(Vec2::new(1., 0.));
+        input.add_scroll(1.5);
+        assert_eq!(input.mouse_delta(), Vec2::new(4., 4.));
+
+        input.end_frame();
+        assert!(input.is_key_pressed(VirtualKeyCode::W));
+        assert_eq!((input.mouse_delta(), input.scroll_delta()), (Vec2::ZERO, 0.));
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert!(!input.is_key_pressed(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn fly_controller_moves_where_it_looks_and_only_turns_while_the_button_is_held() {
+        let start = Transform::from_translation(Vec3::new(0., 0., -5.));
+        let mut fly_controller = FlyController::new(start);
+        fly_controller.settings = unsmoothed(fly_controller.settings);
+        let mut input = CameraInput::new();
+
+        input.add_mouse_motion(Vec2::new(100., 0.));
+        assert_eq!(fly_controller.update(&input, FRAME).rotation, start.rotation);
+
+        input.set_mouse_button(MouseButton::Right, true);
+        // straight up, more than the pitch limit allows
+        input.add_mouse_motion(Vec2::new(-100., -10_000.));
+        let transform = fly_controller.update(&input, FRAME);
+        assert!((transform.forward().y.asin() - MAX_FLY_PITCH).abs() < 1e-4);
+        input.end_frame();
+
+        input.set_key(VirtualKeyCode::W, true);
+        let moved = fly_controller.update(&input, 1.);
+        assert!((moved.translation - transform.translation).abs_diff_eq(transform.forward() * fly_controller.settings.speed, 1e-4));
+    }
+
+    #[test]
+    fn first_person_controller_walks_horizontally_with_clamped_pitch() {
+        let mut first_person_controller = FirstPersonController::new(Transform::default());
+        first_person_controller.settings = unsmoothed(first_person_controller.settings);
+        let mut input = CameraInput::new();
+        input.set_mouse_button(MouseButton::Right, true);
+        input.add_mouse_motion(Vec2::new(0., -10_000.));
+        input.set_key(VirtualKeyCode::W, true);
+        input.set_key(VirtualKeyCode::E, true);
+
+        let transform = first_person_controller.update(&input, 1.);
+        assert!((transform.forward().y.asin() - 85_f32.to_radians()).abs() < 1e-4);
+        assert!(transform.translation.abs_diff_eq(Vec3::Z * first_person_controller.settings.speed, 1e-4));
+    }
+
+    #[test]
+    fn smoothed_controllers_catch_up_over_time() {
+        let mut fly_controller = FlyController::new(Transform::default());
+        let mut input = CameraInput::new();
+        input.set_key(VirtualKeyCode::D, true);
+        let first_frame = fly_controller.update(&input, FRAME);
+        let target_x = fly_controller.settings.speed * FRAME;
+        assert!(first_frame.translation.x > 0. && first_frame.translation.x < target_x);
+
+        input.set_key(VirtualKeyCode::D, false);
+        let mut transform = first_frame;
+        for _ in 0..120 {
+            transform = fly_controller.update(&input, FRAME);
+        }
+        assert!((transform.translation.x - target_x).abs() < 1e-5);
+        // the same amount of time smooths the same amount, no matter how it is split into frames
+        assert!((smoothing_factor(10., 0.2) - (1. - (1. - smoothing_factor(10., 0.1)).powi(2))).abs() < 1e-6);
+    }
+
+    #[test]
+    fn orbit_controller_keeps_looking_at_its_focus() {
+        let focus = Vec3::new(1., 0., 0.);
+        let mut orbit_controller = OrbitController::new(focus, Vec3::new(1., 0., -4.));
+        orbit_controller.responsiveness = f32::INFINITY;
+        let mut input = CameraInput::new();
+        input.set_mouse_button(MouseButton::Left, true);
+        input.add_mouse_motion(Vec2::new(200., 50.));
+        input.add_scroll(2.);
+
+        let transform = orbit_controller.update(&input, FRAME);
+        let expected_distance = 4. * 0.9_f32.powi(2);
+        assert!(((focus - transform.translation).length() - expected_distance).abs() < 1e-4);
+        assert!(transform.forward().abs_diff_eq((focus - transform.translation).normalize(), 1e-5));
+        input.end_frame();
+
+        input.add_scroll(-1000.);
+        let zoomed_out = orbit_controller.update(&input, FRAME);
+        assert!(((focus - zoomed_out.translation).length() - orbit_controller.max_distance).abs() < 1e-2);
+    }
+
+    #[test]
+    fn orbit_controller_pans_its_focus_with_the_mouse() {
+        let mut orbit_controller = OrbitController::new(Vec3::ZERO, Vec3::new(0., 0., -2.));
+        orbit_controller.responsiveness = f32::INFINITY;
+        let mut input = CameraInput::new();
+        input.set_mouse_button(MouseButton::Middle, true);
+        // dragging to the right moves the scene to the right, so the camera goes left
+        input.add_mouse_motion(Vec2::new(100., 0.));
+        let transform = orbit_controller.update(&input, FRAME);
+        assert!(orbit_controller.target_focus().x < 0.);
+        assert_eq!(transform.translation.x, orbit_controller.target_focus().x);
+
+        orbit_controller.reset(Transform::from_translation(Vec3::new(0., 5., 0.)));
+        assert!(orbit_controller.target_focus().abs_diff_eq(Vec3::new(0., 5., orbit_controller.distance), 1e-6));
+    }
+}
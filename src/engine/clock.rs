@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+// 60 simulation steps per second
+pub const DEFAULT_FIXED_STEP: Duration = Duration::from_nanos(16_666_667);
+// if a frame took longer than this many steps the remaining simulation time is dropped, so slow frames can't snowball into even slower ones
+pub const DEFAULT_MAX_STEPS_PER_FRAME: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockAdvance {
+    // number of fixed steps to simulate this frame
+    pub steps: u32,
+    // how far the frame is between the last two simulation states, in [0, 1)
+    pub alpha: f32,
+    // simulation time that didn't fit into max_steps_per_frame and got skipped, zero unless the simulation fell behind
+    pub dropped: Duration,
+}
+
+// decouples the simulation rate from the frame rate: frame time is accumulated and consumed in fixed steps
+#[derive(Debug)]
+pub struct EngineClock {
+    fixed_step: Duration,
+    max_steps_per_frame: u32,
+    accumulator: Duration,
+    last_update: Option<Instant>,
+    last_frame_time: Duration,
+}
+
+impl Default for EngineClock {
+    fn default() -> Self {
+        Self::new(DEFAULT_FIXED_STEP, DEFAULT_MAX_STEPS_PER_FRAME)
+    }
+}
+
+impl EngineClock {
+    pub fn new(fixed_step: Duration, max_steps_per_frame: u32) -> Self {
+        assert!(!fixed_step.is_zero(), "the fixed step has to be longer than zero");
+        Self {
+            fixed_step,
+            max_steps_per_frame,
+            accumulator: Duration::ZERO,
+            last_update: None,
+            last_frame_time: Duration::ZERO
+        }
+    }
+
+    pub fn fixed_step(&self) -> Duration {
+        self.fixed_step
+    }
+
+    pub fn fixed_step_seconds(&self) -> f32 {
+        self.fixed_step.as_secs_f32()
+    }
+
+    // the frame time passed to the last advance, for things that follow the frame rate instead of the fixed steps (e.g. camera controllers)
+    pub fn last_frame_time(&self) -> Duration {
+        self.last_frame_time
+    }
+
+    pub fn advance(&mut self, frame_time: Duration) -> ClockAdvance {
+        self.last_frame_time = frame_time;
+        self.accumulator += frame_time;
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_step && steps < self.max_steps_per_frame {
+            self.accumulator -= self.fixed_step;
+            steps += 1;
+        }
+        let mut dropped = Duration::ZERO;
+        if self.accumulator >= self.fixed_step {
+            let remainder = Duration::from_nanos((self.accumulator.as_nanos() % self.fixed_step.as_nanos()) as u64);
+            dropped = self.accumulator - remainder;
+            self.accumulator = remainder;
+        }
+        ClockAdvance {
+            steps,
+            alpha: self.accumulator.as_secs_f32() / self.fixed_step.as_secs_f32(),
+            dropped
+        }
+    }
+
+    // advances by the wall clock time since the last call, the first call doesn't advance at all
+    pub fn advance_to_now(&mut self) -> ClockAdvance {
+        let now = Instant::now();
+        let frame_time = self.last_update.map(|last_update| now - last_update).unwrap_or(Duration::ZERO);
+        self.last_update = Some(now);
+        self.advance(frame_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock() -> EngineClock {
+        EngineClock::new(Duration::from_millis(10), 4)
+    }
+
+    #[test]
+    fn frame_time_is_consumed_in_fixed_steps() {
+        let mut clock = clock();
+        assert_eq!(clock.advance(Duration::from_millis(25)), ClockAdvance { steps: 2, alpha: 0.5, dropped: Duration::ZERO });
+        // the left over 5ms carry over into the next frame
+        assert_eq!(clock.advance(Duration::from_millis(5)).steps, 1);
+    }
+
+    #[test]
+    fn short_frames_only_move_the_alpha() {
+        let mut clock = clock();
+        let clock_advance = clock.advance(Duration::from_millis(2));
+        assert_eq!(clock_advance.steps, 0);
+        assert!((clock_advance.alpha - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn steps_per_frame_are_clamped_and_the_backlog_dropped() {
+        let mut clock = clock();
+        let clock_advance = clock.advance(Duration::from_millis(103));
+        assert_eq!(clock_advance.steps, 4);
+        assert!((clock_advance.alpha - 0.3).abs() < 1e-6);
+        assert_eq!(clock_advance.dropped, Duration::from_millis(60));
+        assert_eq!(clock.advance(Duration::ZERO).steps, 0);
+    }
+}
@@ -0,0 +1,65 @@
+// the built in components, every other 'static type (lights, trigger volumes, gameplay state, ...) can be attached to an entity as user data via World::insert
+pub use crate::physics::physics_traits::Transform;
+pub use crate::rendering::rendering_traits::Visibility;
+
+use glam::Mat4;
+use serde::{Deserialize, Serialize};
+
+use crate::rendering::mesh_registry::MeshHandle;
+
+use super::entity_id::EntityId;
+use super::scene::SceneId;
+
+// the entity's mesh in the engine's mesh registry, entities sharing a mesh get instanced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshReference {
+    pub mesh_handle: MeshHandle,
+}
+
+impl MeshReference {
+    pub fn new(mesh_handle: MeshHandle) -> Self {
+        Self {
+            mesh_handle
+        }
+    }
+}
+
+// the scene owning the entity, the entity is despawned when its scene gets unloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneMember {
+    pub scene_id: SceneId,
+}
+
+impl SceneMember {
+    pub fn new(scene_id: SceneId) -> Self {
+        Self {
+            scene_id
+        }
+    }
+}
+
+// the transform before the last simulation step, rendering blends between it and the current transform
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviousTransform(pub Transform);
+
+// the entity's Transform is relative to its parent's world transform, see hierarchy::set_parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(pub EntityId);
+
+// kept in sync with Parent by hierarchy::set_parent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Children(pub Vec<EntityId>);
+
+// the world matrix last sent to the renderer, children build theirs on top of their parent's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldTransform(pub Mat4);
+
+// the entity's world transform (and with it its subtree's) has to be recomputed even if its own Transform didn't change, e.g. after re-parenting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransformDirty;
+
+// moves the entity along x by a random speed in [-max_speed, max_speed) units per second every tick, see systems::jitter_system
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Jitter {
+    pub max_speed: f32,
+}
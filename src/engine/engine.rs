@@ -1,92 +1,665 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use glam::Vec3;
-use egui_winit_vulkano::egui::Window;
-use egui_winit_vulkano::Gui;
 use rand::Rng;
-use winit::event_loop::{EventLoop};
 
 use crate::physics::physics_traits::{Transform};
+use crate::rendering::gltf_loader::load_gltf;
+use crate::rendering::obj_loader::load_obj;
+use crate::rendering::mesh_registry::{MeshHandle, MeshRegistry};
 use crate::rendering::primitives::Mesh;
-use crate::rendering::renderer::{EngineEvent, EntityUpdateInfo, HasMovedInfo};
-use crate::rendering::rendering_traits::{HasMesh, RenderableEntity, Visibility};
-use crate::rendering::{{primitives::Cube}, renderer::Renderer, shaders::Shaders};
+use crate::rendering::procedural_meshes::Primitive;
+use crate::rendering::renderer::{ChangedVisibilityInfo, EngineEvent, EntityUpdateInfo, HasMovedInfo};
+use crate::rendering::rendering_traits::Visibility;
+use crate::rendering::renderer::Renderer;
 
+use super::camera::Camera;
+use super::camera_controller::{CameraController, CameraInput};
+use super::clock::{ClockAdvance, EngineClock};
+use super::components::{Jitter, MeshReference, PreviousTransform, SceneMember, WorldTransform};
+use super::entity_id::EntityId;
 use super::general_traits::{TickAction};
-use super::scene::Scene;
-use crate::physics::physics_traits::HasTransform;
+use super::hierarchy;
+use super::scene::{Scene, SceneId};
+use super::scene_description::{CameraDescription, EntityDescription, MeshDescription, MeshDescriptionKey, SceneDescription};
+use super::systems::{jitter_system, System};
+use super::world::World;
 
 pub struct EntityToBufferRegisterData {
-    pub id: String,
+    pub id: EntityId,
     pub mesh: Mesh,
     pub transform: Transform
 }
 
+// a scene the engine keeps. the entities of unloaded scenes stay in the world, but only loaded scenes have theirs on the gpu
+struct SceneEntry {
+    scene: Arc<Scene>,
+    loaded: bool,
+    // the described entities of a scene that was never loaded, they are spawned the first time it gets loaded
+    unspawned_content: Option<SceneDescription>,
+}
+
 pub struct Engine {
-    entities: Vec<Box<dyn RenderableEntity>>,
+    world: World,
+    mesh_registry: MeshRegistry,
+    // where the registered meshes came from, meshes missing here are saved with their data
+    mesh_descriptions: HashMap<MeshHandle, MeshDescription>,
+    // generated and file meshes by their description, so each is only generated or loaded once
+    described_mesh_handles: HashMap<MeshDescriptionKey, MeshHandle>,
+    scenes: HashMap<SceneId, SceneEntry>,
+    active_scene_id: SceneId,
+    next_scene_id: u32,
+    systems: Vec<Box<dyn System>>,
+    clock: EngineClock,
+    // visibility changes of the simulation steps since the last update
+    pending_entity_updates: Vec<EntityUpdateInfo>,
+    // entities whose interpolated transform was sent last update, they need one more (exact) update once they stopped moving
+    interpolated_entities: HashSet<EntityId>,
+    camera_controller: Option<Box<dyn CameraController>>,
+    // the camera transform the controller returned last, if the camera got moved by something else the controller starts over from there
+    controlled_camera_transform: Option<Transform>,
+    // fed with the window's events, the camera controller reads it every update
+    pub camera_input: CameraInput,
     pub next_swapchain_image_index: usize,
-   // scenes: Vec<Arc<Scene>>,
     pub event_queue: Vec<EngineEvent>
 }
 
 impl Engine {
     pub fn new() -> Self {
-        let entities = Vec::new();
+        let world = World::new();
+        let systems: Vec<Box<dyn System>> = vec![Box::new(jitter_system)];
         let event_queue = Vec::new();
+        let active_scene_id = SceneId::new(0);
+        let active_scene = SceneEntry { scene: Arc::new(Scene::new()), loaded: true, unspawned_content: None };
         Self {
-            entities,
+            world,
+            mesh_registry: MeshRegistry::new(),
+            mesh_descriptions: HashMap::new(),
+            described_mesh_handles: HashMap::new(),
+            scenes: HashMap::from([(active_scene_id, active_scene)]),
+            active_scene_id,
+            next_scene_id: 1,
+            systems,
+            clock: EngineClock::default(),
+            pending_entity_updates: Vec::new(),
+            interpolated_entities: HashSet::new(),
+            camera_controller: None,
+            controlled_camera_transform: None,
+            camera_input: CameraInput::new(),
             next_swapchain_image_index: 0,
-           // scenes,
             event_queue
         }
     }
 
+    // replaces the active scene's cameras, its entities stay. see switch_scene for changing to another scene
     pub fn set_active_scene(& mut self, scene: Arc<Scene>) {
+        self.scenes.get_mut(&self.active_scene_id).unwrap().scene = scene.clone();
         self.event_queue.push(EngineEvent::ChangedActiveScene(scene));
     }
 
-    pub fn tick(&mut self) -> () {
-        //self.renderer.camera.as_mut().unwrap().update_position();
-        let mut entities_tick_infos: Vec<EntityUpdateInfo> = Vec::new();
-        for (id, entity) in self.entities.iter_mut().enumerate() {
-            let entity_update_info = entity.tick();
-            match entity_update_info {
-                Some(TickAction::HasMoved(transform)) => { 
-                    let transform_buffer_info = HasMovedInfo {
-                        entity_id: id,
-                        new_transform: transform
-                    };
-                    entities_tick_infos.push(EntityUpdateInfo::HasMoved(transform_buffer_info));
-                },
-                Some(TickAction::ChangedVisibility(visbility)) => {
-                    entities_tick_infos.push(EntityUpdateInfo::ChangedVisibility(visbility));
+    // moves the active scene's camera, the rest of the scene stays
+    pub fn set_active_camera(&mut self, camera: Camera) {
+        let scene_entry = self.scenes.get_mut(&self.active_scene_id).unwrap();
+        Arc::make_mut(&mut scene_entry.scene).camera = camera.clone();
+        self.event_queue.push(EngineEvent::ChangedActiveCamera(camera));
+    }
+
+    // the controller moves the active camera every update, None leaves the camera where it is
+    pub fn set_camera_controller(&mut self, camera_controller: Option<Box<dyn CameraController>>) {
+        self.camera_controller = camera_controller;
+        self.controlled_camera_transform = None;
+    }
+
+    pub fn active_scene(&self) -> &Arc<Scene> {
+        &self.scenes[&self.active_scene_id].scene
+    }
+
+    // new entities are added to the active scene
+    pub fn active_scene_id(&self) -> SceneId {
+        self.active_scene_id
+    }
+
+    pub fn scene(&self, scene_id: SceneId) -> Option<&Arc<Scene>> {
+        Some(&self.scenes.get(&scene_id)?.scene)
+    }
+
+    pub fn scene_ids(&self) -> Vec<SceneId> {
+        let mut scene_ids: Vec<SceneId> = self.scenes.keys().copied().collect();
+        scene_ids.sort();
+        scene_ids
+    }
+
+    pub fn is_scene_loaded(&self, scene_id: SceneId) -> bool {
+        self.scenes.get(&scene_id).is_some_and(|scene_entry| scene_entry.loaded)
+    }
+
+    pub fn scene_entities(&self, scene_id: SceneId) -> Vec<EntityId> {
+        self.world.query::<SceneMember>()
+            .filter(|(_, scene_member)| scene_member.scene_id == scene_id)
+            .map(|(entity_id, _)| entity_id)
+            .collect()
+    }
+
+    // adds an empty scene, it is not loaded until it is switched to or loaded additively
+    pub fn add_scene(&mut self, scene: Arc<Scene>) -> SceneId {
+        self.add_scene_entry(scene, None)
+    }
+
+    // adds a scene with the described entities, they are spawned once the scene gets loaded.
+    // the described camera becomes the scene's camera, scenes without one get the default camera
+    pub fn add_scene_description(&mut self, scene_description: SceneDescription) -> SceneId {
+        let scene = match &scene_description.camera {
+            Some(camera_description) => Scene::with_cameras(vec![Camera::from(camera_description)]).unwrap(),
+            None => Scene::new(),
+        };
+        self.add_scene_entry(Arc::new(scene), Some(scene_description))
+    }
+
+    // adds the scene saved at path (see add_scene_description), unlike load_scene nothing is spawned yet
+    pub fn open_scene(&mut self, path: &Path) -> Result<SceneId, Box<dyn Error>> {
+        let scene_description = SceneDescription::from_json(&fs::read_to_string(path)?)?;
+        Ok(self.add_scene_description(scene_description))
+    }
+
+    fn add_scene_entry(&mut self, scene: Arc<Scene>, unspawned_content: Option<SceneDescription>) -> SceneId {
+        let scene_id = SceneId::new(self.next_scene_id);
+        self.next_scene_id += 1;
+        self.scenes.insert(scene_id, SceneEntry { scene, loaded: false, unspawned_content });
+        scene_id
+    }
+
+    // loads the scene and makes it the active one, every other loaded scene (including additively loaded ones) gets unloaded.
+    // meshes used by both scenes stay on the gpu, the others are freed and the vertex buffers compacted
+    pub fn switch_scene(&mut self, scene_id: SceneId) -> Result<(), Box<dyn Error>> {
+        // loading first, so a scene that fails to load leaves the current scenes untouched
+        self.load_scene_content(scene_id)?;
+        let unloaded_scene_ids: Vec<SceneId> = self.scene_ids().into_iter()
+            .filter(|loaded_scene_id| *loaded_scene_id != scene_id && self.is_scene_loaded(*loaded_scene_id))
+            .collect();
+        for unloaded_scene_id in unloaded_scene_ids {
+            self.unload_scene_content(unloaded_scene_id);
+        }
+        self.active_scene_id = scene_id;
+        self.event_queue.push(EngineEvent::ChangedActiveScene(self.active_scene().clone()));
+        self.event_queue.push(EngineEvent::UnloadUnusedMeshes(true));
+        Ok(())
+    }
+
+    // loads the scene next to the already loaded ones (e.g. an overlay or a streamed in sub level), the active scene and camera stay.
+    // returns the scene's entities, none if the scene was already loaded
+    pub fn load_scene_additive(&mut self, scene_id: SceneId) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        self.load_scene_content(scene_id)
+    }
+
+    // the scene's entities stay in the world with their ids and components (systems keep running on them), only their gpu data is freed until the scene is loaded again.
+    // false for the active scene and scenes that aren't loaded
+    pub fn unload_scene(&mut self, scene_id: SceneId) -> bool {
+        if scene_id == self.active_scene_id || !self.is_scene_loaded(scene_id) {
+            return false;
+        }
+        self.unload_scene_content(scene_id);
+        self.event_queue.push(EngineEvent::UnloadUnusedMeshes(false));
+        true
+    }
+
+    // forgets the scene, its entities are despawned without keeping anything. false for the active scene and unknown scenes
+    pub fn remove_scene(&mut self, scene_id: SceneId) -> bool {
+        if scene_id == self.active_scene_id || self.scenes.remove(&scene_id).is_none() {
+            return false;
+        }
+        for entity_id in self.scene_entities(scene_id) {
+            self.remove_entity(entity_id);
+        }
+        self.event_queue.push(EngineEvent::UnloadUnusedMeshes(false));
+        true
+    }
+
+    fn load_scene_content(&mut self, scene_id: SceneId) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        let scene_entry = self.scenes.get(&scene_id).ok_or(format!("there is no scene {:?}", scene_id))?;
+        if scene_entry.loaded {
+            return Ok(Vec::new());
+        }
+        // spawned while the scene is still unloaded, so they reach the renderer below like the entities of a scene loaded again
+        if let Some(unspawned_content) = scene_entry.unspawned_content.clone() {
+            self.spawn_scene_description_into(scene_id, &unspawned_content)?;
+            self.scenes.get_mut(&scene_id).unwrap().unspawned_content = None;
+        }
+        self.scenes.get_mut(&scene_id).unwrap().loaded = true;
+        let entity_ids = self.scene_entities(scene_id);
+        for entity_id in entity_ids.iter() {
+            self.queue_entity_added(*entity_id);
+        }
+        Ok(entity_ids)
+    }
+
+    fn unload_scene_content(&mut self, scene_id: SceneId) {
+        for entity_id in self.scene_entities(scene_id) {
+            if self.world.get::<MeshReference>(entity_id).is_some() {
+                self.event_queue.push(EngineEvent::EntityRemoved(entity_id));
+            }
+        }
+        self.scenes.get_mut(&scene_id).unwrap().loaded = false;
+    }
+
+    // sends an entity with a mesh to the renderer, where it is in the world right now
+    fn queue_entity_added(&mut self, entity_id: EntityId) {
+        let Some(mesh_reference) = self.world.get::<MeshReference>(entity_id) else { return };
+        let mesh = self.mesh_registry.get(mesh_reference.mesh_handle).unwrap().clone();
+        let transform = Transform::from_matrix(hierarchy::world_matrix(&self.world, entity_id));
+        self.event_queue.push(EngineEvent::EntityAdded(transform, mesh, entity_id));
+        if self.world.get::<Visibility>(entity_id) == Some(&Visibility::Invisible) {
+            let changed_visibility_info = ChangedVisibilityInfo {
+                entity_id,
+                new_visibility: Visibility::Invisible
+            };
+            self.event_queue.push(EngineEvent::EntitiesUpdated(vec![EntityUpdateInfo::ChangedVisibility(changed_visibility_info)]));
+        }
+    }
+
+    // the renderer only knows the entities of loaded scenes
+    fn is_entity_loaded(&self, entity_id: EntityId) -> bool {
+        self.world.get::<SceneMember>(entity_id).is_some_and(|scene_member| self.is_scene_loaded(scene_member.scene_id))
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    // components can be changed freely, but transform and mesh changes only reach the renderer through systems or the engine methods below
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn mesh_registry(&self) -> &MeshRegistry {
+        &self.mesh_registry
+    }
+
+    // meshes with the same content as an already registered one get that mesh's handle
+    pub fn register_mesh(&mut self, mesh: Mesh) -> MeshHandle {
+        self.mesh_registry.register(mesh)
+    }
+
+    pub fn add_system(&mut self, system: Box<dyn System>) {
+        self.systems.push(system);
+    }
+
+    pub fn fixed_step(&self) -> Duration {
+        self.clock.fixed_step()
+    }
+
+    // runs as many fixed simulation steps as fit into frame_time and queues the transforms blended between the last two steps for rendering
+    pub fn update(&mut self, frame_time: Duration) -> ClockAdvance {
+        let clock_advance = self.clock.advance(frame_time);
+        self.run_steps_and_queue_updates(clock_advance);
+        clock_advance
+    }
+
+    // same as update, with the wall clock time since the last call as frame time
+    pub fn update_to_now(&mut self) -> ClockAdvance {
+        let clock_advance = self.clock.advance_to_now();
+        self.run_steps_and_queue_updates(clock_advance);
+        clock_advance
+    }
+
+    fn run_steps_and_queue_updates(&mut self, clock_advance: ClockAdvance) {
+        for _ in 0..clock_advance.steps {
+            self.tick(self.clock.fixed_step_seconds());
+        }
+        self.queue_interpolated_transforms(clock_advance.alpha);
+        self.update_camera_controller(self.clock.last_frame_time().as_secs_f32());
+    }
+
+    // runs once per frame (not per simulation step), so the camera follows the input as smoothly as the frame rate allows
+    fn update_camera_controller(&mut self, frame_time: f32) {
+        let mut camera = self.active_scene().camera.clone();
+        let Some(camera_controller) = self.camera_controller.as_mut() else { return };
+        if self.controlled_camera_transform != Some(camera.transform()) {
+            camera_controller.reset(camera.transform());
+        }
+        let camera_transform = camera_controller.update(&self.camera_input, frame_time);
+        self.camera_input.end_frame();
+        self.controlled_camera_transform = Some(camera_transform);
+        if camera_transform != camera.transform() {
+            camera.set_transform(camera_transform);
+            self.set_active_camera(camera);
+        }
+    }
+
+    // one fixed simulation step
+    pub fn tick(&mut self, delta_time: f32) -> () {
+        self.world.query_pair_mut::<PreviousTransform, Transform>(|_, previous_transform, transform| previous_transform.0 = *transform);
+        for system in self.systems.iter_mut() {
+            for (id, tick_action) in system.run(&mut self.world, delta_time) {
+                match tick_action {
+                    TickAction::HasMoved(transform) => {
+                        if let Some(current_transform) = self.world.get_mut::<Transform>(id) {
+                            *current_transform = transform;
+                        }
+                    },
+                    TickAction::ChangedVisibility(visbility) => {
+                        self.world.insert(id, visbility);
+                        let changed_visibility_info = ChangedVisibilityInfo {
+                            entity_id: id,
+                            new_visibility: visbility
+                        };
+                        self.pending_entity_updates.push(EntityUpdateInfo::ChangedVisibility(changed_visibility_info));
+                    }
                 }
-                None => {},
             }
         }
-        self.event_queue.push(EngineEvent::EntitiesUpdated(entities_tick_infos)); 
     }
 
-    pub fn add_cube_to_scene(&mut self, translation: Option<Vec3>) -> () {
-        match translation {
-            Some(translation) => {
-                let mut cube = Box::new(Cube::new(Vec3{ x: 0.25, y: 0.25, z: 0.25 }, Transform { translation, ..Default::default()}));
-                let mesh = cube.get_mesh("Cube".to_owned());
-                let entity_index = self.entities.len();
-                self.event_queue.push(EngineEvent::EntityAdded(cube.get_transform(), mesh, entity_index));
-                self.entities.push(cube);
+    // moved entities take their children along, so the whole subtree below a moved entity gets new world transforms
+    // entities of unloaded scenes are left out, the renderer gets their current state once their scene is loaded again
+    fn queue_interpolated_transforms(&mut self, alpha: f32) {
+        let mut entities_update_infos = std::mem::take(&mut self.pending_entity_updates);
+        entities_update_infos.retain(|entity_update_info| match entity_update_info {
+            EntityUpdateInfo::HasMoved(has_moved_info) => self.is_entity_loaded(has_moved_info.entity_id),
+            EntityUpdateInfo::ChangedVisibility(changed_visibility_info) => self.is_entity_loaded(changed_visibility_info.entity_id),
+        });
+        let previously_interpolated_entities = std::mem::take(&mut self.interpolated_entities);
+        let mut interpolated_entities = HashSet::new();
+        let mut changed_entities = HashSet::new();
+        self.world.query_pair_mut::<PreviousTransform, Transform>(|id, previous_transform, transform| {
+            let is_moving = previous_transform.0 != *transform;
+            if is_moving || previously_interpolated_entities.contains(&id) {
+                changed_entities.insert(id);
             }
+            if is_moving {
+                interpolated_entities.insert(id);
+            }
+        });
+        self.interpolated_entities = interpolated_entities;
+        for (id, world_matrix) in hierarchy::propagate_world_transforms(&mut self.world, &changed_entities, alpha) {
+            // entities without a mesh (e.g. pivots) only exist for their children
+            if self.world.get::<MeshReference>(id).is_none() || !self.is_entity_loaded(id) {
+                continue;
+            }
+            let transform_buffer_info = HasMovedInfo {
+                entity_id: id,
+                new_transform: Transform::from_matrix(world_matrix)
+            };
+            entities_update_infos.push(EntityUpdateInfo::HasMoved(transform_buffer_info));
+        }
+        if !entities_update_infos.is_empty() {
+            self.event_queue.push(EngineEvent::EntitiesUpdated(entities_update_infos));
+        }
+    }
+
+    pub fn add_cube_to_scene(&mut self, translation: Option<Vec3>) -> EntityId {
+        let translation = match translation {
+            Some(translation) => translation,
             None => {
                 let rand_x: f32 = rand::thread_rng().gen_range(-0.5_f32..0.5_f32);
                 let rand_y: f32 = rand::thread_rng().gen_range(-0.5_f32..1_f32);
                 let rand_z: f32 = rand::thread_rng().gen_range(-2_f32..-0.7_f32);
-                let mut cube: Box<Cube> = Box::new(Cube::new(Vec3{ x: 0.25, y: 0.25, z: 0.25 }, Transform { translation: Vec3 { x: rand_x, y: rand_y, z: rand_z }, ..Default::default() }));
-                let mesh = cube.get_mesh("Cube".to_owned());
-                let entity_index: usize = self.entities.len();
-                self.event_queue.push(EngineEvent::EntityAdded(cube.get_transform(), mesh, entity_index));
-                self.entities.push(cube);
+                Vec3 { x: rand_x, y: rand_y, z: rand_z }
             }
         };
+        let transform = Transform { translation, ..Default::default()};
+        let cube_mesh_handle = self.described_mesh_handle(MeshDescription::Cube { bounds: [0.25; 3] }).unwrap();
+        let entity_id = self.add_mesh_instance_to_scene(cube_mesh_handle, transform).unwrap();
+        self.world.insert(entity_id, Jitter { max_speed: 1.2 });
+        entity_id
+    }
+
+    // e.g. add_primitive_to_scene(Primitive::UvSphere { radius: 0.5, segments: 32, rings: 16 }, transform)
+    pub fn add_primitive_to_scene(&mut self, primitive: Primitive, transform: Transform) -> EntityId {
+        let mesh_handle = self.described_mesh_handle(MeshDescription::Primitive { primitive }).unwrap();
+        self.add_mesh_instance_to_scene(mesh_handle, transform).unwrap()
+    }
+
+    // generates or loads the described mesh the first time it is asked for
+    fn described_mesh_handle(&mut self, mesh_description: MeshDescription) -> Result<MeshHandle, Box<dyn Error>> {
+        let Some(description_key) = mesh_description.key() else {
+            return Ok(self.register_mesh(mesh_description.load_mesh()?));
+        };
+        if let Some(mesh_handle) = self.described_mesh_handles.get(&description_key) {
+            return Ok(*mesh_handle);
+        }
+        let mesh_handle = self.register_mesh(mesh_description.load_mesh()?);
+        self.described_mesh_handles.insert(description_key, mesh_handle);
+        self.mesh_descriptions.entry(mesh_handle).or_insert(mesh_description);
+        Ok(mesh_handle)
+    }
+
+    // children are removed along with their parent, returns false if the entity doesn't exist (anymore), e.g. for stale ids of already removed entities
+    pub fn remove_entity(&mut self, entity_id: EntityId) -> bool {
+        if !self.world.is_alive(entity_id) {
+            return false;
+        }
+        hierarchy::set_parent(&mut self.world, entity_id, None, false);
+        for removed_entity_id in std::iter::once(entity_id).chain(hierarchy::descendants(&self.world, entity_id)) {
+            // the renderer only knows the entities with a mesh of loaded scenes
+            if self.world.get::<MeshReference>(removed_entity_id).is_some() && self.is_entity_loaded(removed_entity_id) {
+                self.event_queue.push(EngineEvent::EntityRemoved(removed_entity_id));
+            }
+            self.world.despawn(removed_entity_id);
+        }
+        true
+    }
+
+    // attaches the entity to parent (None detaches it), with keep_world_pose it stays where it is instead of moving along with its new parent's transform.
+    // returns false for stale ids and if the entity would become its own ancestor
+    pub fn set_parent(&mut self, entity_id: EntityId, parent: Option<EntityId>, keep_world_pose: bool) -> bool {
+        hierarchy::set_parent(&mut self.world, entity_id, parent, keep_world_pose)
+    }
+
+    pub fn parent(&self, entity_id: EntityId) -> Option<EntityId> {
+        hierarchy::parent(&self.world, entity_id)
+    }
+
+    pub fn children(&self, entity_id: EntityId) -> &[EntityId] {
+        hierarchy::children(&self.world, entity_id)
+    }
+
+    // an entity without a mesh, e.g. a pivot other entities are attached to
+    pub fn add_empty_to_scene(&mut self, transform: Transform) -> EntityId {
+        self.spawn_entity(self.active_scene_id, transform)
+    }
+
+    fn spawn_entity(&mut self, scene_id: SceneId, transform: Transform) -> EntityId {
+        let entity_id = self.world.spawn();
+        self.world.insert(entity_id, SceneMember::new(scene_id));
+        self.world.insert(entity_id, transform);
+        self.world.insert(entity_id, PreviousTransform(transform));
+        self.world.insert(entity_id, WorldTransform(transform.matrix()));
+        entity_id
+    }
+
+    // hidden entities keep their gpu data but are left out of the draws, returns false for stale ids
+    pub fn set_visibility(&mut self, entity_id: EntityId, visibility: Visibility) -> bool {
+        if !self.world.is_alive(entity_id) {
+            return false;
+        }
+        self.world.insert(entity_id, visibility);
+        // entities of unloaded scenes get their visibility along with their gpu data once the scene is loaded again
+        if self.is_entity_loaded(entity_id) {
+            let changed_visibility_info = ChangedVisibilityInfo {
+                entity_id,
+                new_visibility: visibility
+            };
+            self.event_queue.push(EngineEvent::EntitiesUpdated(vec![EntityUpdateInfo::ChangedVisibility(changed_visibility_info)]));
+        }
+        true
+    }
+
+    pub fn contains_entity(&self, entity_id: EntityId) -> bool {
+        self.world.is_alive(entity_id)
+    }
+
+    // registers the mesh (see register_mesh) and spawns an entity using it
+    pub fn add_mesh_to_scene(&mut self, mesh: Mesh, transform: Transform) -> EntityId {
+        let mesh_handle = self.register_mesh(mesh);
+        self.add_mesh_instance_to_scene(mesh_handle, transform).unwrap()
+    }
+
+    // returns None if the handle doesn't belong to this engine's mesh registry
+    pub fn add_mesh_instance_to_scene(&mut self, mesh_handle: MeshHandle, transform: Transform) -> Option<EntityId> {
+        self.spawn_mesh_instance(self.active_scene_id, mesh_handle, transform)
+    }
+
+    fn spawn_mesh_instance(&mut self, scene_id: SceneId, mesh_handle: MeshHandle, transform: Transform) -> Option<EntityId> {
+        let mesh = self.mesh_registry.get(mesh_handle)?.clone();
+        let entity_id = self.spawn_entity(scene_id, transform);
+        self.world.insert(entity_id, MeshReference::new(mesh_handle));
+        self.world.insert(entity_id, Visibility::Visible);
+        if self.is_scene_loaded(scene_id) {
+            self.event_queue.push(EngineEvent::EntityAdded(transform, mesh, entity_id));
+        }
+        Some(entity_id)
+    }
+
+    // spawns one entity per object/group in the obj file, all with the same transform
+    pub fn add_obj_to_scene(&mut self, path: &Path, transform: Transform) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        let mut entity_ids = Vec::new();
+        for mesh in load_obj(path)? {
+            let mesh_description = MeshDescription::Obj { path: path.to_path_buf(), object: mesh.get_name().clone() };
+            let mesh_handle = self.register_mesh(mesh);
+            self.described_mesh_handles.extend(mesh_description.key().map(|description_key| (description_key, mesh_handle)));
+            self.mesh_descriptions.entry(mesh_handle).or_insert(mesh_description);
+            entity_ids.push(self.add_mesh_instance_to_scene(mesh_handle, transform).unwrap());
+        }
+        Ok(entity_ids)
+    }
+
+    // spawns an entity for every node in the gltf scene, parented like the nodes, and returns one entity per node.
+    // nodes with several primitives get an entity without a mesh with one child per primitive.
+    // the imported cameras are added to the active scene's cameras, the active camera stays
+    pub fn add_gltf_to_scene(&mut self, path: &Path) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        let import = load_gltf(path)?;
+        let mut node_entity_ids: Vec<EntityId> = Vec::new();
+        for node in import.nodes {
+            let node_entity_id = match <[Mesh; 1]>::try_from(node.meshes) {
+                Ok([mesh]) => self.add_mesh_to_scene(mesh, node.transform),
+                Err(meshes) => {
+                    let node_entity_id = self.add_empty_to_scene(node.transform);
+                    for mesh in meshes {
+                        let primitive_entity_id = self.add_mesh_to_scene(mesh, Transform::default());
+                        self.set_parent(primitive_entity_id, Some(node_entity_id), false);
+                    }
+                    node_entity_id
+                }
+            };
+            if let Some(parent_index) = node.parent {
+                self.set_parent(node_entity_id, Some(node_entity_ids[parent_index]), false);
+            }
+            node_entity_ids.push(node_entity_id);
+        }
+        if !import.cameras.is_empty() {
+            let scene_entry = self.scenes.get_mut(&self.active_scene_id).unwrap();
+            Arc::make_mut(&mut scene_entry.scene).cameras.extend(import.cameras);
+            self.event_queue.push(EngineEvent::ChangedActiveScene(scene_entry.scene.clone()));
+        }
+        Ok(node_entity_ids)
+    }
+
+    // the active scene's entities with a mesh, with the meshes they use and the scene's camera
+    pub fn scene_description(&self) -> SceneDescription {
+        self.describe_scene(self.active_scene_id)
+    }
+
+    // scenes that were never loaded are described by the description they were added with
+    pub fn describe_scene(&self, scene_id: SceneId) -> SceneDescription {
+        let Some(scene_entry) = self.scenes.get(&scene_id) else { return SceneDescription::default() };
+        if let Some(unspawned_content) = &scene_entry.unspawned_content {
+            return unspawned_content.clone();
+        }
+        let mut scene_description = SceneDescription {
+            camera: Some(CameraDescription::from(&scene_entry.scene.camera)),
+            ..Default::default()
+        };
+        let mut mesh_indexes: HashMap<MeshHandle, usize> = HashMap::new();
+        let mut entity_indexes: HashMap<EntityId, usize> = HashMap::new();
+        for (entity_id, mesh_reference) in self.world.query::<MeshReference>() {
+            if self.world.get::<SceneMember>(entity_id).map(|scene_member| scene_member.scene_id) != Some(scene_id) {
+                continue;
+            }
+            entity_indexes.insert(entity_id, scene_description.entities.len());
+            let mesh_index = *mesh_indexes.entry(mesh_reference.mesh_handle).or_insert_with(|| {
+                let mesh_description = match self.mesh_descriptions.get(&mesh_reference.mesh_handle) {
+                    Some(mesh_description) => mesh_description.clone(),
+                    None => MeshDescription::inline(self.mesh_registry.get(mesh_reference.mesh_handle).unwrap()),
+                };
+                scene_description.meshes.push(mesh_description);
+                scene_description.meshes.len() - 1
+            });
+            scene_description.entities.push(EntityDescription {
+                mesh: mesh_index,
+                transform: self.world.get::<Transform>(entity_id).copied().unwrap_or_default().into(),
+                visible: self.world.get::<Visibility>(entity_id) != Some(&Visibility::Invisible),
+                jitter: self.world.get::<Jitter>(entity_id).copied(),
+                parent: None,
+            });
+        }
+        // entities whose parent isn't saved (e.g. a pivot without a mesh) are saved where they are in the world
+        for (entity_id, entity_index) in entity_indexes.iter() {
+            let Some(parent_id) = hierarchy::parent(&self.world, *entity_id) else { continue };
+            let entity_description = &mut scene_description.entities[*entity_index];
+            match entity_indexes.get(&parent_id) {
+                Some(parent_index) => entity_description.parent = Some(*parent_index),
+                None => entity_description.transform = Transform::from_matrix(hierarchy::world_matrix(&self.world, *entity_id)).into(),
+            }
+        }
+        scene_description
+    }
+
+    pub fn save_scene(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.scene_description().to_json()?)?;
+        Ok(())
+    }
+
+    // adds the scene's entities to the active scene, its camera (if it has one) becomes the active camera. see open_scene for loading it as its own scene
+    pub fn load_scene(&mut self, path: &Path) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        let scene_description = SceneDescription::from_json(&fs::read_to_string(path)?)?;
+        self.spawn_scene_description(&scene_description)
+    }
+
+    // spawns into the active scene
+    pub fn spawn_scene_description(&mut self, scene_description: &SceneDescription) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        self.spawn_scene_description_into(self.active_scene_id, scene_description)
+    }
+
+    // all meshes are loaded before the first entity is spawned, so a broken mesh reference doesn't leave a half loaded scene behind
+    fn spawn_scene_description_into(&mut self, scene_id: SceneId, scene_description: &SceneDescription) -> Result<Vec<EntityId>, Box<dyn Error>> {
+        let mesh_handles = scene_description.meshes.iter()
+            .map(|mesh_description| self.described_mesh_handle(mesh_description.clone()))
+            .collect::<Result<Vec<MeshHandle>, Box<dyn Error>>>()?;
+        let mesh_handle = |mesh_index: usize| mesh_handles.get(mesh_index).copied().ok_or(format!("entity references mesh {mesh_index} but the scene only has {} meshes", mesh_handles.len()));
+        let entity_mesh_handles = scene_description.entities.iter()
+            .map(|entity_description| mesh_handle(entity_description.mesh))
+            .collect::<Result<Vec<MeshHandle>, String>>()?;
+        scene_description.validate_parents()?;
+
+        let mut entity_ids = Vec::new();
+        for (entity_description, mesh_handle) in scene_description.entities.iter().zip(entity_mesh_handles) {
+            let entity_id = self.spawn_mesh_instance(scene_id, mesh_handle, entity_description.transform.into()).unwrap();
+            if !entity_description.visible {
+                self.set_visibility(entity_id, Visibility::Invisible);
+            }
+            if let Some(jitter) = entity_description.jitter {
+                self.world.insert(entity_id, jitter);
+            }
+            entity_ids.push(entity_id);
+        }
+        for (entity_description, entity_id) in scene_description.entities.iter().zip(entity_ids.iter()) {
+            if let Some(parent_index) = entity_description.parent {
+                self.set_parent(*entity_id, Some(entity_ids[parent_index]), false);
+            }
+        }
+        if let Some(camera_description) = &scene_description.camera {
+            let scene = Arc::new(Scene::with_cameras(vec![Camera::from(camera_description)]).unwrap());
+            self.scenes.get_mut(&scene_id).unwrap().scene = scene.clone();
+            if scene_id == self.active_scene_id {
+                self.event_queue.push(EngineEvent::ChangedActiveScene(scene));
+            }
+        }
+        Ok(entity_ids)
     }
 
     pub fn add_cubes_to_scene(&mut self, translations: Vec<Option<Vec3>>) -> () {
@@ -95,16 +668,342 @@ impl Engine {
 
     pub fn work_off_event_queue(&mut self, renderer: & mut Renderer, swapchain_image_index: usize) {
         //println!("working of event queue for image with index: {}", self.next_swapchain_image_index);
-        let len = self.event_queue.len();
-        //work off the events
-        for _ in 0..len {
-            match self.event_queue.pop() { // ToDo: decide if fifo or lifo is the right way, for now lifo seems to work
-                Some(EngineEvent::EntityAdded(entity_transform, entity_mesh, entity_index)) => renderer.entity_added_handler(entity_transform, entity_mesh, entity_index, swapchain_image_index),
-                Some(EngineEvent::ChangedActiveScene(active_scene)) => renderer.changed_active_scene_handler(active_scene),
-                //Some(RendererEvent::SynchBuffers(entity, most_up_to_date_buffer_index)) => self.synch_buffers_handler(most_up_to_date_buffer_index, entity),
-                Some(EngineEvent::EntitiesUpdated(updated_entities_infos)) => renderer.entities_updated_handler(updated_entities_infos),
-                _ => ()
+        //work off the events, fifo since e.g. an entity has to be added before it can be removed
+        for event in self.event_queue.drain(..) {
+            match event {
+                EngineEvent::EntityAdded(entity_transform, entity_mesh, entity_id) => renderer.entity_added_handler(entity_transform, entity_mesh, entity_id),
+                EngineEvent::EntityRemoved(entity_id) => renderer.entity_removed_handler(entity_id, swapchain_image_index),
+                EngineEvent::ChangedActiveScene(active_scene) => renderer.changed_active_scene_handler(active_scene),
+                //RendererEvent::SynchBuffers(entity, most_up_to_date_buffer_index) => self.synch_buffers_handler(most_up_to_date_buffer_index, entity),
+                EngineEvent::EntitiesUpdated(updated_entities_infos) => renderer.entities_updated_handler(updated_entities_infos),
+                EngineEvent::UnloadUnusedMeshes(compact) => renderer.unload_unused_meshes(compact),
+                EngineEvent::ChangedActiveCamera(camera) => renderer.changed_active_camera_handler(camera),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::camera_controller::FlyController;
+    use crate::engine::scene_description::TransformDescription;
+
+    fn moved_translations_x(engine: &mut Engine) -> Vec<f32> {
+        let mut translations_x = Vec::new();
+        for event in engine.event_queue.drain(..) {
+            if let EngineEvent::EntitiesUpdated(entities_update_infos) = event {
+                for entity_update_info in entities_update_infos {
+                    if let EntityUpdateInfo::HasMoved(has_moved_info) = entity_update_info {
+                        translations_x.push(has_moved_info.new_transform.translation.x);
+                    }
+                }
             }
         }
+        translations_x
+    }
+
+    #[test]
+    fn rendered_transforms_are_interpolated_between_simulation_steps() {
+        let mut engine = Engine::new();
+        let entity_id = engine.add_mesh_to_scene(Mesh::new(Vec::new(), Vec::new(), "Empty".to_owned()), Transform::default());
+        // one unit per second along x
+        engine.add_system(Box::new(move |world: &mut World, delta_time: f32| {
+            let mut transform = *world.get::<Transform>(entity_id).unwrap();
+            transform.translation.x += delta_time;
+            vec![(entity_id, TickAction::HasMoved(transform))]
+        }));
+        engine.event_queue.clear();
+
+        let step = engine.fixed_step();
+        let clock_advance = engine.update(step * 2 + step / 2);
+        assert_eq!(clock_advance.steps, 2);
+        let translations_x = moved_translations_x(&mut engine);
+        assert_eq!(translations_x.len(), 1);
+        assert!((translations_x[0] - 1.5 * step.as_secs_f32()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn resting_entities_get_one_last_exact_update() {
+        let mut engine = Engine::new();
+        let entity_id = engine.add_mesh_to_scene(Mesh::new(Vec::new(), Vec::new(), "Empty".to_owned()), Transform::default());
+        engine.event_queue.clear();
+        let step = engine.fixed_step();
+
+        let mut moved = Transform::default();
+        moved.translation.x = 1.;
+        engine.world_mut().insert(entity_id, moved);
+        engine.update(step / 2);
+        let translations_x = moved_translations_x(&mut engine);
+        assert_eq!(translations_x.len(), 1);
+        assert!((translations_x[0] - 0.5).abs() < 1e-5);
+
+        // the next step makes previous and current transform equal, the entity still needs to end up exactly at x = 1
+        engine.update(step);
+        assert_eq!(moved_translations_x(&mut engine), vec![1.]);
+        engine.update(step);
+        assert!(moved_translations_x(&mut engine).is_empty());
+    }
+
+    #[test]
+    fn visibility_changes_are_stored_and_sent_to_the_renderer() {
+        let mut engine = Engine::new();
+        let entity_id = engine.add_mesh_to_scene(Mesh::new(Vec::new(), Vec::new(), "Empty".to_owned()), Transform::default());
+        assert_eq!(engine.world().get::<Visibility>(entity_id), Some(&Visibility::Visible));
+        engine.event_queue.clear();
+
+        assert!(engine.set_visibility(entity_id, Visibility::Invisible));
+        assert_eq!(engine.world().get::<Visibility>(entity_id), Some(&Visibility::Invisible));
+        let Some(EngineEvent::EntitiesUpdated(entities_update_infos)) = engine.event_queue.pop() else { panic!("expected an update event") };
+        assert!(matches!(
+            entities_update_infos.as_slice(),
+            [EntityUpdateInfo::ChangedVisibility(ChangedVisibilityInfo { entity_id: id, new_visibility: Visibility::Invisible })] if *id == entity_id
+        ));
+
+        engine.remove_entity(entity_id);
+        assert!(!engine.set_visibility(entity_id, Visibility::Visible));
+    }
+
+    #[test]
+    fn spawned_cubes_share_one_registered_mesh() {
+        let mut engine = Engine::new();
+        let first_cube = engine.add_cube_to_scene(None);
+        let second_cube = engine.add_cube_to_scene(None);
+
+        assert_eq!(engine.mesh_registry().len(), 1);
+        let mesh_handle = engine.world().get::<MeshReference>(first_cube).unwrap().mesh_handle;
+        assert_eq!(engine.world().get::<MeshReference>(second_cube).unwrap().mesh_handle, mesh_handle);
+        let added_meshes: Vec<Arc<Mesh>> = engine.event_queue.drain(..).filter_map(|event| match event {
+            EngineEvent::EntityAdded(_, mesh, _) => Some(mesh),
+            _ => None,
+        }).collect();
+        assert_eq!(added_meshes.len(), 2);
+        assert!(Arc::ptr_eq(&added_meshes[0], &added_meshes[1]));
+    }
+
+    #[test]
+    fn primitives_are_generated_once_per_kind_and_parameters() {
+        let mut engine = Engine::new();
+        let sphere = Primitive::UvSphere { radius: 0.5, segments: 16, rings: 8 };
+        let first_sphere = engine.add_primitive_to_scene(sphere, Transform::default());
+        let second_sphere = engine.add_primitive_to_scene(sphere, Transform::default());
+        engine.add_primitive_to_scene(Primitive::UvSphere { radius: 1., segments: 16, rings: 8 }, Transform::default());
+        engine.add_primitive_to_scene(Primitive::Torus { major_radius: 1., minor_radius: 0.25, major_segments: 24, minor_segments: 12 }, Transform::default());
+
+        assert_eq!(engine.mesh_registry().len(), 3);
+        assert_eq!(engine.world().get::<MeshReference>(first_sphere), engine.world().get::<MeshReference>(second_sphere));
+    }
+
+    #[test]
+    fn saved_scenes_load_into_the_same_scene() {
+        let mut engine = Engine::new();
+        engine.add_cube_to_scene(Some(Vec3::new(1., 2., 3.)));
+        engine.add_cube_to_scene(None);
+        let sphere = engine.add_primitive_to_scene(Primitive::UvSphere { radius: 0.5, segments: 16, rings: 8 }, Transform::default());
+        engine.set_visibility(sphere, Visibility::Invisible);
+        engine.add_mesh_to_scene(Mesh::new(vec![Default::default(); 3], vec![0, 1, 2], "Triangle".to_owned()), Transform::default());
+        let scene_path = std::env::temp_dir().join(format!("saved_scene_{}.json", std::process::id()));
+        engine.save_scene(&scene_path).unwrap();
+
+        let mut loaded_engine = Engine::new();
+        let entity_ids = loaded_engine.load_scene(&scene_path).unwrap();
+        fs::remove_file(&scene_path).unwrap();
+        assert_eq!(entity_ids.len(), 4);
+        assert_eq!(loaded_engine.scene_description(), engine.scene_description());
+        assert_eq!(loaded_engine.scene_description().meshes.len(), 3);
+        assert_eq!(loaded_engine.world().get::<Visibility>(entity_ids[2]), Some(&Visibility::Invisible));
+    }
+
+    fn sphere_scene_description() -> SceneDescription {
+        SceneDescription {
+            meshes: vec![MeshDescription::Primitive { primitive: Primitive::UvSphere { radius: 0.5, segments: 16, rings: 8 } }],
+            entities: vec![EntityDescription { mesh: 0, transform: TransformDescription { translation: [0., 1., 0.], ..Default::default() }, visible: true, jitter: None, parent: None }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn switching_scenes_swaps_the_loaded_entities() {
+        let mut engine = Engine::new();
+        let first_scene_id = engine.active_scene_id();
+        let cube = engine.add_cube_to_scene(Some(Vec3::new(1., 2., 3.)));
+        let second_scene_id = engine.add_scene_description(sphere_scene_description());
+        assert!(!engine.is_scene_loaded(second_scene_id));
+        engine.event_queue.clear();
+
+        engine.switch_scene(second_scene_id).unwrap();
+        assert_eq!(engine.active_scene_id(), second_scene_id);
+        assert!(!engine.is_scene_loaded(first_scene_id));
+        // the cube stays in the world, only the renderer drops it
+        assert!(engine.contains_entity(cube));
+        let spheres = engine.scene_entities(second_scene_id);
+        assert_eq!(spheres.len(), 1);
+        assert_eq!(engine.world().get::<Transform>(spheres[0]).unwrap().translation, Vec3::new(0., 1., 0.));
+        let events = std::mem::take(&mut engine.event_queue);
+        assert!(matches!(events.as_slice(), [
+            EngineEvent::EntityAdded(..),
+            EngineEvent::EntityRemoved(removed),
+            EngineEvent::ChangedActiveScene(_),
+            EngineEvent::UnloadUnusedMeshes(true),
+        ] if *removed == cube));
+
+        // switching back sends the cube to the renderer again, where it was
+        engine.switch_scene(first_scene_id).unwrap();
+        assert_eq!(engine.scene_entities(first_scene_id), vec![cube]);
+        assert_eq!(engine.world().get::<Transform>(cube).unwrap().translation, Vec3::new(1., 2., 3.));
+        assert!(engine.world().get::<Jitter>(cube).is_some());
+        let events = std::mem::take(&mut engine.event_queue);
+        assert!(matches!(events.as_slice(), [
+            EngineEvent::EntityAdded(transform, _, added),
+            EngineEvent::EntityRemoved(removed),
+            EngineEvent::ChangedActiveScene(_),
+            EngineEvent::UnloadUnusedMeshes(true),
+        ] if *added == cube && transform.translation.abs_diff_eq(Vec3::new(1., 2., 3.), 1e-6) && *removed == spheres[0]));
+        assert_eq!(engine.scene_entities(second_scene_id), spheres);
+        assert_eq!(engine.describe_scene(second_scene_id).entities, sphere_scene_description().entities);
+    }
+
+    #[test]
+    fn additively_loaded_scenes_stay_until_unloaded() {
+        let mut engine = Engine::new();
+        let active_scene_id = engine.active_scene_id();
+        engine.add_cube_to_scene(None);
+        let overlay_scene_id = engine.add_scene_description(sphere_scene_description());
+
+        let overlay_entities = engine.load_scene_additive(overlay_scene_id).unwrap();
+        assert_eq!(overlay_entities.len(), 1);
+        assert!(engine.load_scene_additive(overlay_scene_id).unwrap().is_empty());
+        assert_eq!(engine.active_scene_id(), active_scene_id);
+        assert_eq!(engine.scene_entities(active_scene_id).len(), 1);
+        // only the active scene is saved
+        assert_eq!(engine.scene_description().entities.len(), 1);
+
+        assert!(!engine.unload_scene(active_scene_id));
+        assert!(engine.unload_scene(overlay_scene_id));
+        assert!(!engine.unload_scene(overlay_scene_id));
+        assert!(engine.contains_entity(overlay_entities[0]));
+        assert_eq!(engine.scene_entities(active_scene_id).len(), 1);
+        assert!(matches!(engine.event_queue.last(), Some(EngineEvent::UnloadUnusedMeshes(false))));
+
+        assert!(engine.remove_scene(overlay_scene_id));
+        assert!(!engine.contains_entity(overlay_entities[0]));
+        assert!(engine.load_scene_additive(overlay_scene_id).is_err());
+        assert_eq!(engine.scene_ids(), vec![active_scene_id]);
+    }
+
+    #[test]
+    fn entities_and_their_hierarchy_survive_switching_away_and_back() {
+        let mut engine = Engine::new();
+        let first_scene_id = engine.active_scene_id();
+        let pivot = engine.add_empty_to_scene(Transform::from_translation(Vec3::new(2., 0., 0.)));
+        let cube = engine.add_cube_to_scene(Some(Vec3::new(0., 1., 0.)));
+        engine.set_parent(cube, Some(pivot), false);
+        engine.set_visibility(cube, Visibility::Invisible);
+        let second_scene_id = engine.add_scene(Arc::new(Scene::new()));
+        engine.switch_scene(second_scene_id).unwrap();
+        engine.event_queue.clear();
+
+        // the jittering cube keeps moving, but nothing of an unloaded scene reaches the renderer
+        engine.update(engine.fixed_step() * 3);
+        assert!(engine.event_queue.is_empty());
+        let cube_translation = engine.world().get::<Transform>(cube).unwrap().translation + Vec3::new(2., 0., 0.);
+
+        engine.switch_scene(first_scene_id).unwrap();
+        assert!(engine.contains_entity(pivot) && engine.contains_entity(cube));
+        assert_eq!(engine.parent(cube), Some(pivot));
+        let events = std::mem::take(&mut engine.event_queue);
+        // only the cube has a mesh, it comes back at its world transform and still hidden
+        let added: Vec<(EntityId, Vec3)> = events.iter().filter_map(|event| match event {
+            EngineEvent::EntityAdded(transform, _, entity_id) => Some((*entity_id, transform.translation)),
+            _ => None,
+        }).collect();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].0, cube);
+        assert!(added[0].1.abs_diff_eq(cube_translation, 1e-5));
+        assert!(events.iter().any(|event| matches!(event, EngineEvent::EntitiesUpdated(entities_update_infos) if matches!(
+            entities_update_infos.as_slice(),
+            [EntityUpdateInfo::ChangedVisibility(ChangedVisibilityInfo { entity_id, new_visibility: Visibility::Invisible })] if *entity_id == cube
+        ))));
+    }
+
+    #[test]
+    fn moving_a_parent_updates_its_children_and_removing_it_removes_them() {
+        let mut engine = Engine::new();
+        let character = engine.add_empty_to_scene(Transform::default());
+        let weapon = engine.add_mesh_to_scene(Mesh::new(Vec::new(), Vec::new(), "Weapon".to_owned()), Transform::from_translation(Vec3::new(0., 1., 0.)));
+        assert!(engine.set_parent(weapon, Some(character), false));
+        engine.event_queue.clear();
+
+        engine.world_mut().get_mut::<Transform>(character).unwrap().translation.x = 2.;
+        engine.update(engine.fixed_step() / 2);
+        // only the weapon has a mesh, the character is just a pivot. halfway between x = 0 and x = 2
+        let translations_x = moved_translations_x(&mut engine);
+        assert_eq!(translations_x.len(), 1);
+        assert!((translations_x[0] - 1.).abs() < 1e-5);
+
+        assert!(engine.remove_entity(character));
+        assert!(!engine.contains_entity(weapon));
+        // the character never reached the renderer, so only the weapon gets removed there
+        let removed_entity_ids: Vec<EntityId> = engine.event_queue.iter().filter_map(|event| match event {
+            EngineEvent::EntityRemoved(entity_id) => Some(*entity_id),
+            _ => None,
+        }).collect();
+        assert_eq!(removed_entity_ids, vec![weapon]);
+    }
+
+    #[test]
+    fn gltf_node_trees_are_imported_as_entity_hierarchies() {
+        let mut engine = Engine::new();
+        let gltf_path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("gltf").join("triangle_hierarchy.gltf");
+        let node_entity_ids = engine.add_gltf_to_scene(&gltf_path).unwrap();
+        assert_eq!(node_entity_ids.len(), 4);
+        let cameras = &engine.active_scene().cameras;
+        assert_eq!(cameras.len(), 2);
+        assert!(cameras[1].transform().translation.abs_diff_eq(Vec3::new(1., 0., -5.), 1e-6));
+
+        let meshes: Vec<EntityId> = engine.world().query::<MeshReference>().map(|(entity_id, _)| entity_id).collect();
+        assert_eq!(meshes.len(), 2);
+        let root = engine.parent(meshes[0]).unwrap();
+        assert_eq!(engine.children(root).len(), 2);
+        assert_eq!(engine.parent(meshes[1]), None);
+        let child_world_translation = hierarchy::world_matrix(engine.world(), meshes[0]).w_axis.truncate();
+        assert!(child_world_translation.abs_diff_eq(Vec3::new(1., 2., 0.), 1e-6));
+
+        // the root has no mesh, so the child is saved where it is in the world
+        let scene_description = engine.scene_description();
+        assert_eq!(scene_description.entities[0].parent, None);
+        assert!(Vec3::from(scene_description.entities[0].transform.translation).abs_diff_eq(Vec3::new(1., 2., 0.), 1e-6));
+    }
+
+    #[test]
+    fn saved_scenes_keep_their_hierarchy() {
+        let mut engine = Engine::new();
+        let parent = engine.add_cube_to_scene(Some(Vec3::new(1., 0., 0.)));
+        let child = engine.add_cube_to_scene(Some(Vec3::new(0., 1., 0.)));
+        engine.set_parent(child, Some(parent), false);
+
+        let mut loaded_engine = Engine::new();
+        let entity_ids = loaded_engine.spawn_scene_description(&engine.scene_description()).unwrap();
+        assert_eq!(loaded_engine.parent(entity_ids[1]), Some(entity_ids[0]));
+        assert_eq!(loaded_engine.world().get::<Transform>(entity_ids[1]).unwrap().translation, Vec3::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn camera_controllers_move_the_active_camera() {
+        let mut engine = Engine::new();
+        engine.set_camera_controller(Some(Box::new(FlyController::new(Transform::default()))));
+        let start = engine.active_scene().camera.transform();
+        engine.event_queue.clear();
+
+        // nothing pressed, the controller picks up the scene's camera and leaves it there
+        engine.update(engine.fixed_step());
+        assert_eq!(engine.active_scene().camera.transform(), start);
+        assert!(engine.event_queue.is_empty());
+
+        engine.camera_input.set_key(winit::event::VirtualKeyCode::W, true);
+        engine.update(engine.fixed_step());
+        let moved = engine.active_scene().camera.transform();
+        assert!(moved.translation.z > start.translation.z);
+        assert!(matches!(engine.event_queue.as_slice(), [EngineEvent::ChangedActiveCamera(camera)] if camera.transform() == moved));
     }
 }
@@ -0,0 +1,200 @@
+// generational index: the index of a freed entity gets reused, the generation tells the old and new handle apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+// hands out EntityIds, the engine owns the only instance
+#[derive(Debug, Default)]
+pub struct EntityIdAllocator {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_indices: Vec<u32>,
+}
+
+impl EntityIdAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allocate(&mut self) -> EntityId {
+        match self.free_indices.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                EntityId { index, generation: self.generations[index as usize] }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                EntityId { index: (self.generations.len() - 1) as u32, generation: 0 }
+            }
+        }
+    }
+
+    // returns false for stale or already freed ids
+    pub fn free(&mut self, entity_id: EntityId) -> bool {
+        if !self.is_alive(entity_id) {
+            return false;
+        }
+        self.alive[entity_id.index()] = false;
+        self.generations[entity_id.index()] += 1;
+        self.free_indices.push(entity_id.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity_id: EntityId) -> bool {
+        self.alive.get(entity_id.index()).copied().unwrap_or(false) && self.generations[entity_id.index()] == entity_id.generation
+    }
+}
+
+// O(1) EntityId -> T lookup, indexed by EntityId::index, lookups with stale ids return None
+#[derive(Debug, Clone)]
+pub struct EntityMap<T> {
+    entries: Vec<Option<(u32, T)>>,
+    len: usize,
+}
+
+impl<T> Default for EntityMap<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            len: 0
+        }
+    }
+}
+
+impl<T> EntityMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // returns the value that was stored for the same index before (which might have belonged to an older generation)
+    pub fn insert(&mut self, entity_id: EntityId, value: T) -> Option<T> {
+        if self.entries.len() <= entity_id.index() {
+            self.entries.resize_with(entity_id.index() + 1, || None);
+        }
+        let previous = self.entries[entity_id.index()].replace((entity_id.generation, value)).map(|(_, value)| value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    pub fn get(&self, entity_id: EntityId) -> Option<&T> {
+        match self.entries.get(entity_id.index()) {
+            Some(Some((generation, value))) if *generation == entity_id.generation => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut T> {
+        match self.entries.get_mut(entity_id.index()) {
+            Some(Some((generation, value))) if *generation == entity_id.generation => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId) -> Option<T> {
+        let entry = self.entries.get_mut(entity_id.index())?;
+        match entry {
+            Some((generation, _)) if *generation == entity_id.generation => {
+                self.len -= 1;
+                entry.take().map(|(_, value)| value)
+            }
+            _ => None
+        }
+    }
+
+    pub fn contains(&self, entity_id: EntityId) -> bool {
+        self.get(entity_id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.entries.iter().enumerate().filter_map(|(index, entry)| {
+            entry.as_ref().map(|(generation, value)| (EntityId { index: index as u32, generation: *generation }, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.entries.iter_mut().enumerate().filter_map(|(index, entry)| {
+            entry.as_mut().map(|(generation, value)| (EntityId { index: index as u32, generation: *generation }, value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_indices_are_reused_with_a_new_generation() {
+        let mut allocator = EntityIdAllocator::new();
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        assert_ne!(first, second);
+
+        assert!(allocator.free(first));
+        let reused = allocator.allocate();
+        assert_eq!(reused.index(), first.index());
+        assert_eq!(reused.generation(), first.generation() + 1);
+        assert!(!allocator.is_alive(first));
+        assert!(allocator.is_alive(reused));
+    }
+
+    #[test]
+    fn freeing_twice_fails() {
+        let mut allocator = EntityIdAllocator::new();
+        let entity_id = allocator.allocate();
+        assert!(allocator.free(entity_id));
+        assert!(!allocator.free(entity_id));
+    }
+
+    #[test]
+    fn map_lookups_with_stale_ids_fail() {
+        let mut allocator = EntityIdAllocator::new();
+        let mut map = EntityMap::new();
+        let stale = allocator.allocate();
+        map.insert(stale, "old");
+        allocator.free(stale);
+        map.remove(stale);
+
+        let current = allocator.allocate();
+        map.insert(current, "new");
+        assert_eq!(map.get(stale), None);
+        assert_eq!(map.remove(stale), None);
+        assert_eq!(map.get(current), Some(&"new"));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn map_iterates_over_live_entries_only() {
+        let mut allocator = EntityIdAllocator::new();
+        let mut map = EntityMap::new();
+        let ids: Vec<EntityId> = (0..3).map(|_| allocator.allocate()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            map.insert(*id, i);
+        }
+        map.remove(ids[1]);
+        let remaining: Vec<(EntityId, &usize)> = map.iter().collect();
+        assert_eq!(remaining, vec![(ids[0], &0), (ids[2], &2)]);
+    }
+}
@@ -8,7 +8,8 @@ pub enum TickAction {
 }
 
 pub trait Entity {
-    fn tick(&mut self) -> Option<TickAction>;
+    // delta_time is the simulated time since the last tick in seconds
+    fn tick(&mut self, delta_time: f32) -> Option<TickAction>;
 }
 
 pub trait RegisterToBuffer {
@@ -0,0 +1,229 @@
+// parent/child relations between entities. an entity's Transform is local, relative to its parent's world transform (or the world for entities without a parent)
+use std::collections::HashSet;
+
+use glam::Mat4;
+
+use super::components::{Children, Parent, PreviousTransform, Transform, TransformDirty, WorldTransform};
+use super::entity_id::EntityId;
+use super::world::World;
+
+pub fn parent(world: &World, entity_id: EntityId) -> Option<EntityId> {
+    world.get::<Parent>(entity_id).map(|parent| parent.0)
+}
+
+pub fn children(world: &World, entity_id: EntityId) -> &[EntityId] {
+    world.get::<Children>(entity_id).map(|children| children.0.as_slice()).unwrap_or(&[])
+}
+
+// children, grandchildren and so on, parents before their children
+pub fn descendants(world: &World, entity_id: EntityId) -> Vec<EntityId> {
+    let mut descendants = children(world, entity_id).to_vec();
+    let mut i = 0;
+    while i < descendants.len() {
+        descendants.extend_from_slice(children(world, descendants[i]));
+        i += 1;
+    }
+    descendants
+}
+
+pub fn is_ancestor(world: &World, ancestor: EntityId, entity_id: EntityId) -> bool {
+    let mut current = entity_id;
+    while let Some(parent_id) = parent(world, current) {
+        if parent_id == ancestor {
+            return true;
+        }
+        current = parent_id;
+    }
+    false
+}
+
+fn local_matrix(world: &World, entity_id: EntityId) -> Mat4 {
+    world.get::<Transform>(entity_id).map(Transform::matrix).unwrap_or(Mat4::IDENTITY)
+}
+
+// from the current transforms of the entity and its ancestors, unlike WorldTransform which is blended between simulation steps
+pub fn world_matrix(world: &World, entity_id: EntityId) -> Mat4 {
+    let mut matrix = local_matrix(world, entity_id);
+    let mut current = entity_id;
+    while let Some(parent_id) = parent(world, current) {
+        matrix = local_matrix(world, parent_id) * matrix;
+        current = parent_id;
+    }
+    matrix
+}
+
+// attaches the entity to new_parent, None detaches it. with keep_world_pose the entity's transform is changed so it stays where it is,
+// otherwise it keeps its transform and ends up relative to the new parent. false for stale ids and if the entity would become its own ancestor
+pub fn set_parent(world: &mut World, entity_id: EntityId, new_parent: Option<EntityId>, keep_world_pose: bool) -> bool {
+    if !world.is_alive(entity_id) {
+        return false;
+    }
+    if let Some(parent_id) = new_parent {
+        if !world.is_alive(parent_id) || parent_id == entity_id || is_ancestor(world, entity_id, parent_id) {
+            return false;
+        }
+    }
+    let entity_world_matrix = world_matrix(world, entity_id);
+    if let Some(Parent(old_parent_id)) = world.remove::<Parent>(entity_id) {
+        if let Some(children) = world.get_mut::<Children>(old_parent_id) {
+            children.0.retain(|child_id| *child_id != entity_id);
+        }
+    }
+    if let Some(parent_id) = new_parent {
+        world.insert(entity_id, Parent(parent_id));
+        match world.get_mut::<Children>(parent_id) {
+            Some(children) => children.0.push(entity_id),
+            None => { world.insert(parent_id, Children(vec![entity_id])); }
+        }
+    }
+    if keep_world_pose {
+        let parent_world_matrix = new_parent.map(|parent_id| world_matrix(world, parent_id)).unwrap_or(Mat4::IDENTITY);
+        let transform = Transform::from_matrix(parent_world_matrix.inverse() * entity_world_matrix);
+        world.insert(entity_id, transform);
+        // blending between the transforms relative to the old and the new parent would make the entity jump around
+        if world.get::<PreviousTransform>(entity_id).is_some() {
+            world.insert(entity_id, PreviousTransform(transform));
+        }
+    }
+    world.insert(entity_id, TransformDirty);
+    true
+}
+
+// recomputes the WorldTransform of the changed entities (the given ones and the ones flagged TransformDirty) and all their descendants.
+// local transforms are blended between the last two simulation steps like the rendered transforms, unchanged subtrees keep their cached world matrices.
+// returns the new world matrices, parents before their children
+pub fn propagate_world_transforms(world: &mut World, changed_entities: &HashSet<EntityId>, alpha: f32) -> Vec<(EntityId, Mat4)> {
+    let mut dirty_entities = changed_entities.clone();
+    let flagged_entities: Vec<EntityId> = world.query::<TransformDirty>().map(|(entity_id, _)| entity_id).collect();
+    for entity_id in flagged_entities {
+        world.remove::<TransformDirty>(entity_id);
+        dirty_entities.insert(entity_id);
+    }
+
+    // a walk starts at the topmost dirty entity of each subtree and covers the rest of it
+    let mut subtree_roots: Vec<EntityId> = dirty_entities.iter()
+        .filter(|entity_id| world.is_alive(**entity_id) && !has_dirty_ancestor(world, **entity_id, &dirty_entities))
+        .copied()
+        .collect();
+    subtree_roots.sort();
+
+    let mut world_matrices = Vec::new();
+    for subtree_root in subtree_roots {
+        let parent_world_matrix = parent(world, subtree_root)
+            .and_then(|parent_id| world.get::<WorldTransform>(parent_id))
+            .map(|world_transform| world_transform.0)
+            .unwrap_or(Mat4::IDENTITY);
+        let mut stack = vec![(subtree_root, parent_world_matrix)];
+        while let Some((entity_id, parent_world_matrix)) = stack.pop() {
+            let world_matrix = parent_world_matrix * interpolated_local_matrix(world, entity_id, alpha);
+            world.insert(entity_id, WorldTransform(world_matrix));
+            world_matrices.push((entity_id, world_matrix));
+            stack.extend(children(world, entity_id).iter().rev().map(|child_id| (*child_id, world_matrix)));
+        }
+    }
+    world_matrices
+}
+
+fn has_dirty_ancestor(world: &World, entity_id: EntityId, dirty_entities: &HashSet<EntityId>) -> bool {
+    let mut current = entity_id;
+    while let Some(parent_id) = parent(world, current) {
+        if dirty_entities.contains(&parent_id) {
+            return true;
+        }
+        current = parent_id;
+    }
+    false
+}
+
+fn interpolated_local_matrix(world: &World, entity_id: EntityId, alpha: f32) -> Mat4 {
+    match (world.get::<PreviousTransform>(entity_id), world.get::<Transform>(entity_id)) {
+        (Some(previous_transform), Some(transform)) => previous_transform.0.slerp(transform, alpha).matrix(),
+        _ => local_matrix(world, entity_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use super::*;
+
+    fn spawn_at(world: &mut World, translation: Vec3) -> EntityId {
+        let entity_id = world.spawn();
+        world.insert(entity_id, Transform::from_translation(translation));
+        entity_id
+    }
+
+    fn world_translation(world: &World, entity_id: EntityId) -> Vec3 {
+        world_matrix(world, entity_id).w_axis.truncate()
+    }
+
+    #[test]
+    fn children_move_with_their_parent() {
+        let mut world = World::new();
+        let character = spawn_at(&mut world, Vec3::new(1., 0., 0.));
+        let weapon = spawn_at(&mut world, Vec3::new(0., 1., 0.));
+        assert!(set_parent(&mut world, weapon, Some(character), false));
+        assert!(world_translation(&world, weapon).abs_diff_eq(Vec3::new(1., 1., 0.), 1e-6));
+
+        let turned = Transform { rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2), ..*world.get::<Transform>(character).unwrap() };
+        world.insert(character, turned);
+        assert!(world_translation(&world, weapon).abs_diff_eq(Vec3::new(0., 0., 0.), 1e-6));
+        assert_eq!(children(&world, character), &[weapon]);
+    }
+
+    #[test]
+    fn re_parenting_can_keep_the_world_pose() {
+        let mut world = World::new();
+        let first_parent = spawn_at(&mut world, Vec3::new(1., 0., 0.));
+        let second_parent = spawn_at(&mut world, Vec3::new(0., 0., 3.));
+        world.get_mut::<Transform>(second_parent).unwrap().rotation = Quat::from_rotation_y(1.);
+        let child = spawn_at(&mut world, Vec3::new(0., 2., 0.));
+        set_parent(&mut world, child, Some(first_parent), false);
+        let child_world_matrix = world_matrix(&world, child);
+
+        assert!(set_parent(&mut world, child, Some(second_parent), true));
+        assert!(world_matrix(&world, child).abs_diff_eq(child_world_matrix, 1e-5));
+        assert!(children(&world, first_parent).is_empty());
+        assert_eq!(parent(&world, child), Some(second_parent));
+
+        assert!(set_parent(&mut world, child, None, true));
+        assert!(world_matrix(&world, child).abs_diff_eq(child_world_matrix, 1e-5));
+        assert!(children(&world, second_parent).is_empty());
+    }
+
+    #[test]
+    fn entities_cant_become_their_own_ancestor() {
+        let mut world = World::new();
+        let grandparent = spawn_at(&mut world, Vec3::ZERO);
+        let parent_id = spawn_at(&mut world, Vec3::ZERO);
+        let child = spawn_at(&mut world, Vec3::ZERO);
+        set_parent(&mut world, parent_id, Some(grandparent), false);
+        set_parent(&mut world, child, Some(parent_id), false);
+
+        assert!(!set_parent(&mut world, grandparent, Some(child), false));
+        assert!(!set_parent(&mut world, child, Some(child), false));
+        assert_eq!(parent(&world, grandparent), None);
+        assert_eq!(descendants(&world, grandparent), vec![parent_id, child]);
+    }
+
+    #[test]
+    fn only_changed_subtrees_are_recomputed() {
+        let mut world = World::new();
+        let moving_parent = spawn_at(&mut world, Vec3::new(1., 0., 0.));
+        let moving_child = spawn_at(&mut world, Vec3::new(0., 1., 0.));
+        let resting = spawn_at(&mut world, Vec3::new(5., 0., 0.));
+        set_parent(&mut world, moving_child, Some(moving_parent), false);
+        // the re-parented child is flagged dirty and comes along with its parent
+        let world_matrices = propagate_world_transforms(&mut world, &HashSet::from([moving_parent, resting]), 1.);
+        assert_eq!(world_matrices.iter().map(|(entity_id, _)| *entity_id).collect::<Vec<_>>(), vec![moving_parent, moving_child, resting]);
+        assert!(propagate_world_transforms(&mut world, &HashSet::new(), 1.).is_empty());
+
+        world.get_mut::<Transform>(moving_parent).unwrap().translation.x = 2.;
+        let world_matrices = propagate_world_transforms(&mut world, &HashSet::from([moving_parent]), 1.);
+        assert_eq!(world_matrices.len(), 2);
+        assert_eq!(world_matrices[1].0, moving_child);
+        assert!(world_matrices[1].1.w_axis.truncate().abs_diff_eq(Vec3::new(2., 1., 0.), 1e-6));
+        assert_eq!(world.get::<WorldTransform>(resting).unwrap().0.w_axis.truncate(), Vec3::new(5., 0., 0.));
+    }
+}
@@ -4,8 +4,25 @@ use crate::physics::physics_traits::Transform;
 
 use super::camera::Camera;
 
+// identifies one of the scenes the engine keeps, see Engine::add_scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SceneId(u32);
+
+impl SceneId {
+    pub(crate) fn new(index: u32) -> Self {
+        Self(index)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Clone)]
 pub struct Scene {
-    pub camera: Camera
+    pub camera: Camera,
+    // all cameras of the scene (e.g. imported from gltf), camera is the active one
+    pub cameras: Vec<Camera>,
 }
 
 
@@ -18,7 +35,17 @@ impl Scene {
         let camera = Camera::new(transform, projection_matrix);
 
         Self {
-            camera
+            camera: camera.clone(),
+            cameras: vec![camera]
         }
     }
+
+    // the first camera becomes the active one, None if there are no cameras
+    pub fn with_cameras(cameras: Vec<Camera>) -> Option<Self> {
+        let camera = cameras.first()?.clone();
+        Some(Self {
+            camera,
+            cameras
+        })
+    }
 }
\ No newline at end of file
@@ -0,0 +1,330 @@
+use std::{error::Error, path::PathBuf};
+
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{physics::physics_traits::Transform, rendering::{obj_loader::load_obj, primitives::{Cube, Mesh, Vertex}, procedural_meshes::Primitive, rendering_traits::HasMesh}};
+
+use super::{camera::Camera, components::Jitter};
+
+// bumped whenever a scene file written by an older version can't be read as is anymore, migrate_scene_value then has to upgrade it
+pub const SCENE_FORMAT_VERSION: u32 = 1;
+
+// text (json) description of a scene, see Engine::save_scene and Engine::load_scene.
+// everything but the version is optional, so hand written scene files only need what they use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDescription>,
+    #[serde(default)]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default)]
+    pub entities: Vec<EntityDescription>,
+}
+
+impl Default for SceneDescription {
+    fn default() -> Self {
+        Self {
+            version: SCENE_FORMAT_VERSION,
+            camera: None,
+            meshes: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+}
+
+impl SceneDescription {
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let mut value: Value = serde_json::from_str(json)?;
+        let version = value.get("version").and_then(Value::as_u64).ok_or("scene file has no version")? as u32;
+        if version > SCENE_FORMAT_VERSION {
+            return Err(format!("scene file version {version} is newer than the supported version {SCENE_FORMAT_VERSION}").into());
+        }
+        migrate_scene_value(&mut value, version)?;
+        let scene_description: SceneDescription = serde_json::from_value(value)?;
+        if let Some(entity_description) = scene_description.entities.iter().find(|entity_description| entity_description.mesh >= scene_description.meshes.len()) {
+            return Err(format!("entity references mesh {} but the scene only has {} meshes", entity_description.mesh, scene_description.meshes.len()).into());
+        }
+        scene_description.validate_parents()?;
+        Ok(scene_description)
+    }
+
+    // every parent has to be another entity of the scene and no entity may be its own ancestor
+    pub fn validate_parents(&self) -> Result<(), String> {
+        for (entity_index, entity_description) in self.entities.iter().enumerate() {
+            let mut ancestor_index = entity_description.parent;
+            // an entity has at most entities.len() - 1 ancestors
+            for _ in 0..self.entities.len() {
+                let Some(parent_index) = ancestor_index else { break };
+                if parent_index >= self.entities.len() {
+                    return Err(format!("entity {entity_index} has parent {parent_index} but the scene only has {} entities", self.entities.len()));
+                }
+                if parent_index == entity_index {
+                    return Err(format!("entity {entity_index} is its own ancestor"));
+                }
+                ancestor_index = self.entities[parent_index].parent;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// (version, upgrade to version + 1)
+type SceneMigration = (u32, fn(&mut Value));
+
+// every format change adds its step here
+const SCENE_MIGRATIONS: &[SceneMigration] = &[];
+
+// upgrades a scene file of an older version to SCENE_FORMAT_VERSION one version at a time
+fn migrate_scene_value(value: &mut Value, mut version: u32) -> Result<(), Box<dyn Error>> {
+    while version < SCENE_FORMAT_VERSION {
+        let (_, migrate) = SCENE_MIGRATIONS.iter().find(|(from_version, _)| *from_version == version)
+            .ok_or(format!("scene file version {version} is not supported"))?;
+        migrate(value);
+        version += 1;
+    }
+    value["version"] = SCENE_FORMAT_VERSION.into();
+    Ok(())
+}
+
+// where a mesh comes from, generated and file meshes are stored as such so designers can write them by hand
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum MeshDescription {
+    Cube { bounds: [f32; 3] },
+    Primitive { primitive: Primitive },
+    // one object of an obj file, named "<file stem>/<object name>" like load_obj names them. relative paths are relative to the working directory
+    Obj { path: PathBuf, object: String },
+    // meshes without a known source (e.g. created in code or imported from gltf) are stored with their data
+    Inline { name: String, vertices: Vec<Vertex>, indices: Vec<u32> },
+}
+
+impl MeshDescription {
+    pub fn inline(mesh: &Mesh) -> Self {
+        MeshDescription::Inline {
+            name: mesh.get_name().clone(),
+            vertices: mesh.data.clone(),
+            indices: mesh.indices.clone(),
+        }
+    }
+
+    pub fn load_mesh(&self) -> Result<Mesh, Box<dyn Error>> {
+        match self {
+            MeshDescription::Cube { bounds } => Ok(Cube::new(Vec3::from(*bounds)).get_mesh("Cube".to_owned())),
+            MeshDescription::Primitive { primitive } => Ok(primitive.mesh()),
+            MeshDescription::Obj { path, object } => load_obj(path)?.into_iter()
+                .find(|mesh| mesh.get_name() == object)
+                .ok_or(format!("{:?} has no object {object}", path).into()),
+            MeshDescription::Inline { name, vertices, indices } => Ok(Mesh::new(vertices.clone(), indices.clone(), name.clone())),
+        }
+    }
+
+    // None for inline meshes, the mesh registry recognizes their content instead
+    pub fn key(&self) -> Option<MeshDescriptionKey> {
+        let key = match self {
+            MeshDescription::Cube { bounds } => MeshDescriptionKey::Cube(bounds.map(float_key)),
+            MeshDescription::Primitive { primitive } => MeshDescriptionKey::Primitive(primitive_key(primitive)),
+            MeshDescription::Obj { path, object } => MeshDescriptionKey::Obj(path.clone(), object.clone()),
+            MeshDescription::Inline { .. } => return None,
+        };
+        Some(key)
+    }
+}
+
+// hashable identity of a MeshDescription, descriptions with the same key load the same mesh
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MeshDescriptionKey {
+    Cube([u32; 3]),
+    // variant index and parameters, unused ones are 0
+    Primitive((u8, [u32; 4])),
+    Obj(PathBuf, String),
+}
+
+// floats are keyed by their bits, with -0.0 and 0.0 being the same
+fn float_key(value: f32) -> u32 {
+    match value == 0. {
+        true => 0,
+        false => value.to_bits(),
+    }
+}
+
+fn primitive_key(primitive: &Primitive) -> (u8, [u32; 4]) {
+    match *primitive {
+        Primitive::UvSphere { radius, segments, rings } => (0, [float_key(radius), segments, rings, 0]),
+        Primitive::Icosphere { radius, subdivisions } => (1, [float_key(radius), subdivisions, 0, 0]),
+        Primitive::Plane { width, depth, segments_x, segments_z } => (2, [float_key(width), float_key(depth), segments_x, segments_z]),
+        Primitive::Cylinder { radius, height, segments } => (3, [float_key(radius), float_key(height), segments, 0]),
+        Primitive::Cone { radius, height, segments } => (4, [float_key(radius), float_key(height), segments, 0]),
+        Primitive::Torus { major_radius, minor_radius, major_segments, minor_segments } => (5, [float_key(major_radius), float_key(minor_radius), major_segments, minor_segments]),
+        Primitive::Capsule { radius, height, segments, rings } => (6, [float_key(radius), float_key(height), segments, rings]),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityDescription {
+    // index into SceneDescription::meshes
+    pub mesh: usize,
+    #[serde(default)]
+    pub transform: TransformDescription,
+    #[serde(default = "visible_by_default")]
+    pub visible: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<Jitter>,
+    // index into SceneDescription::entities, transform is relative to the parent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+// rotation is a quaternion (x, y, z, w)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransformDescription {
+    #[serde(default)]
+    pub translation: [f32; 3],
+    #[serde(default = "identity_rotation")]
+    pub rotation: [f32; 4],
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 3],
+}
+
+fn identity_rotation() -> [f32; 4] {
+    Quat::IDENTITY.to_array()
+}
+
+fn unit_scale() -> [f32; 3] {
+    Vec3::ONE.to_array()
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        Self {
+            translation: [0.; 3],
+            rotation: identity_rotation(),
+            scale: unit_scale(),
+        }
+    }
+}
+
+impl From<Transform> for TransformDescription {
+    fn from(transform: Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+        }
+    }
+}
+
+impl From<TransformDescription> for Transform {
+    fn from(transform_description: TransformDescription) -> Self {
+        Transform::new(Vec3::from(transform_description.translation), Quat::from_array(transform_description.rotation), Vec3::from(transform_description.scale))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    #[serde(default)]
+    pub transform: TransformDescription,
+    pub projection: ProjectionDescription,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProjectionDescription {
+    // left handed like the default scene camera, fov_y in radians
+    Perspective { fov_y: f32, aspect_ratio: f32, near: f32, far: f32 },
+    // column major, saved cameras use this since any projection can be stored exactly
+    Matrix { columns: [[f32; 4]; 4] },
+}
+
+impl From<&Camera> for CameraDescription {
+    fn from(camera: &Camera) -> Self {
+        Self {
+            transform: camera.transform().into(),
+            projection: ProjectionDescription::Matrix { columns: camera.projection_matrix().to_cols_array_2d() },
+        }
+    }
+}
+
+impl From<&CameraDescription> for Camera {
+    fn from(camera_description: &CameraDescription) -> Self {
+        let projection_matrix = match camera_description.projection {
+            ProjectionDescription::Perspective { fov_y, aspect_ratio, near, far } => Mat4::perspective_lh(fov_y, aspect_ratio, near, far),
+            ProjectionDescription::Matrix { columns } => Mat4::from_cols_array_2d(&columns),
+        };
+        Camera::new(camera_description.transform.into(), projection_matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_survive_a_json_round_trip() {
+        let scene_description = SceneDescription {
+            camera: Some(CameraDescription {
+                transform: TransformDescription { translation: [0., 1., -5.], ..Default::default() },
+                projection: ProjectionDescription::Perspective { fov_y: 1., aspect_ratio: 1.5, near: 0.1, far: 100. },
+            }),
+            meshes: vec![
+                MeshDescription::Cube { bounds: [0.25; 3] },
+                MeshDescription::Primitive { primitive: Primitive::Torus { major_radius: 1., minor_radius: 0.25, major_segments: 24, minor_segments: 12 } },
+                MeshDescription::Obj { path: PathBuf::from("models/tree.obj"), object: "tree/trunk".to_owned() },
+                MeshDescription::inline(&Mesh::new(vec![Vertex::default(); 3], vec![0, 1, 2], "Triangle".to_owned())),
+            ],
+            entities: vec![
+                EntityDescription { mesh: 0, transform: TransformDescription { translation: [1., 2., 3.], ..Default::default() }, visible: true, jitter: Some(Jitter { max_speed: 1.2 }), parent: None },
+                EntityDescription { mesh: 3, transform: TransformDescription::default(), visible: false, jitter: None, parent: Some(0) },
+            ],
+            ..Default::default()
+        };
+        let json = scene_description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&json).unwrap(), scene_description);
+    }
+
+    #[test]
+    fn mesh_description_keys_ignore_the_sign_of_zero() {
+        let key = |bounds| MeshDescription::Cube { bounds }.key();
+        assert_eq!(key([0.25, -0., 1.]), key([0.25, 0., 1.]));
+        assert_ne!(key([0.25, 0., 1.]), key([0.25, 0., 2.]));
+        let cylinder = MeshDescription::Primitive { primitive: Primitive::Cylinder { radius: 1., height: 2., segments: 8 } };
+        let cone = MeshDescription::Primitive { primitive: Primitive::Cone { radius: 1., height: 2., segments: 8 } };
+        assert_ne!(cylinder.key(), cone.key());
+        assert_eq!(MeshDescription::inline(&Mesh::new(Vec::new(), Vec::new(), "Empty".to_owned())).key(), None);
+    }
+
+    #[test]
+    fn hand_written_scenes_only_need_what_they_use() {
+        let json = r#"{
+            "version": 1,
+            "meshes": [{ "source": "primitive", "primitive": { "kind": "UvSphere", "radius": 0.5, "segments": 16, "rings": 8 } }],
+            "entities": [{ "mesh": 0, "transform": { "translation": [0, 1, 0] } }]
+        }"#;
+        let scene_description = SceneDescription::from_json(json).unwrap();
+        assert_eq!(scene_description.camera, None);
+        assert_eq!(scene_description.entities[0].transform, TransformDescription { translation: [0., 1., 0.], ..Default::default() });
+        assert!(scene_description.entities[0].visible);
+    }
+
+    #[test]
+    fn unsupported_and_inconsistent_scene_files_are_rejected() {
+        assert!(SceneDescription::from_json(r#"{ "meshes": [] }"#).is_err());
+        assert!(SceneDescription::from_json(&format!(r#"{{ "version": {} }}"#, SCENE_FORMAT_VERSION + 1)).is_err());
+        assert!(SceneDescription::from_json(r#"{ "version": 0 }"#).is_err());
+        assert!(SceneDescription::from_json(r#"{ "version": 1, "entities": [{ "mesh": 0 }] }"#).is_err());
+        let cube_mesh = r#"{ "source": "cube", "bounds": [1, 1, 1] }"#;
+        assert!(SceneDescription::from_json(&format!(r#"{{ "version": 1, "meshes": [{cube_mesh}], "entities": [{{ "mesh": 0, "parent": 1 }}] }}"#)).is_err());
+        assert!(SceneDescription::from_json(&format!(r#"{{ "version": 1, "meshes": [{cube_mesh}], "entities": [{{ "mesh": 0, "parent": 1 }}, {{ "mesh": 0, "parent": 0 }}] }}"#)).is_err());
+        assert!(SceneDescription::from_json(&format!(r#"{{ "version": 1, "meshes": [{cube_mesh}], "entities": [{{ "mesh": 0 }}, {{ "mesh": 0, "parent": 0 }}] }}"#)).is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+use rand::Rng;
+
+use super::components::{Jitter, Transform};
+use super::entity_id::EntityId;
+use super::general_traits::TickAction;
+use super::world::World;
+
+// systems run once per fixed simulation step with the step length in seconds, the returned actions tell the renderer which entities need their gpu data updated
+pub trait System {
+    fn run(&mut self, world: &mut World, delta_time: f32) -> Vec<(EntityId, TickAction)>;
+}
+
+impl<F: FnMut(&mut World, f32) -> Vec<(EntityId, TickAction)>> System for F {
+    fn run(&mut self, world: &mut World, delta_time: f32) -> Vec<(EntityId, TickAction)> {
+        self(world, delta_time)
+    }
+}
+
+pub fn jitter_system(world: &mut World, delta_time: f32) -> Vec<(EntityId, TickAction)> {
+    let mut rng = rand::thread_rng();
+    let mut tick_actions = Vec::new();
+    world.query_pair_mut::<Jitter, Transform>(|entity_id, jitter, transform| {
+        transform.translation.x += rng.gen_range(-jitter.max_speed..jitter.max_speed) * delta_time;
+        tick_actions.push((entity_id, TickAction::HasMoved(*transform)));
+    });
+    tick_actions
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn jitter_only_moves_jittering_entities_within_their_speed() {
+        let mut world = World::new();
+        let jittering = world.spawn();
+        let static_entity = world.spawn();
+        let start = Vec3 { x: 1., y: 2., z: 3. };
+        world.insert(jittering, Transform { translation: start, ..Default::default() });
+        world.insert(jittering, Jitter { max_speed: 5. });
+        world.insert(static_entity, Transform { translation: start, ..Default::default() });
+
+        let tick_actions = jitter_system(&mut world, 0.1);
+
+        assert_eq!(tick_actions.len(), 1);
+        let (moved_entity, TickAction::HasMoved(new_transform)) = &tick_actions[0] else { panic!("expected a move") };
+        assert_eq!(*moved_entity, jittering);
+        assert!((new_transform.translation.x - start.x).abs() < 0.5);
+        assert_eq!((new_transform.translation.y, new_transform.translation.z), (start.y, start.z));
+        assert_eq!(world.get::<Transform>(jittering).unwrap().translation, new_transform.translation);
+        assert_eq!(world.get::<Transform>(static_entity).unwrap().translation, start);
+    }
+}
@@ -0,0 +1,270 @@
+use std::{any::{Any, TypeId}, collections::HashMap};
+
+use super::entity_id::{EntityId, EntityIdAllocator};
+
+// sparse set: sparse maps an entity index to a position in the densely packed components, so iterating only touches live components
+pub struct ComponentStorage<T> {
+    sparse: Vec<Option<usize>>,
+    dense_entity_ids: Vec<EntityId>,
+    dense_components: Vec<T>,
+}
+
+impl<T> Default for ComponentStorage<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense_entity_ids: Vec::new(),
+            dense_components: Vec::new()
+        }
+    }
+}
+
+impl<T> ComponentStorage<T> {
+    fn dense_index(&self, entity_id: EntityId) -> Option<usize> {
+        let dense_index = (*self.sparse.get(entity_id.index())?)?;
+        match self.dense_entity_ids[dense_index] == entity_id {
+            true => Some(dense_index),
+            false => None
+        }
+    }
+
+    // replaces (and returns) the entity's component if it already had one
+    pub fn insert(&mut self, entity_id: EntityId, component: T) -> Option<T> {
+        if let Some(dense_index) = self.dense_index(entity_id) {
+            return Some(std::mem::replace(&mut self.dense_components[dense_index], component));
+        }
+        if self.sparse.len() <= entity_id.index() {
+            self.sparse.resize(entity_id.index() + 1, None);
+        }
+        self.sparse[entity_id.index()] = Some(self.dense_components.len());
+        self.dense_entity_ids.push(entity_id);
+        self.dense_components.push(component);
+        None
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId) -> Option<T> {
+        let dense_index = self.dense_index(entity_id)?;
+        self.sparse[entity_id.index()] = None;
+        let last_entity_id = *self.dense_entity_ids.last().unwrap();
+        if last_entity_id != entity_id {
+            self.sparse[last_entity_id.index()] = Some(dense_index);
+        }
+        self.dense_entity_ids.swap_remove(dense_index);
+        Some(self.dense_components.swap_remove(dense_index))
+    }
+
+    pub fn get(&self, entity_id: EntityId) -> Option<&T> {
+        self.dense_index(entity_id).map(|dense_index| &self.dense_components[dense_index])
+    }
+
+    pub fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut T> {
+        self.dense_index(entity_id).map(|dense_index| &mut self.dense_components[dense_index])
+    }
+
+    pub fn contains(&self, entity_id: EntityId) -> bool {
+        self.dense_index(entity_id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense_components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense_components.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.dense_entity_ids.iter().copied().zip(self.dense_components.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.dense_entity_ids.iter().copied().zip(self.dense_components.iter_mut())
+    }
+}
+
+// type erased storage, so the world can hold storages for any component type
+trait AnyComponentStorage {
+    fn remove_entity(&mut self, entity_id: EntityId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyComponentStorage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity_id: EntityId) {
+        self.remove(entity_id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// entities are just ids, all their data lives in one component storage per component type
+#[derive(Default)]
+pub struct World {
+    entity_id_allocator: EntityIdAllocator,
+    storages: HashMap<TypeId, Box<dyn AnyComponentStorage>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> EntityId {
+        self.entity_id_allocator.allocate()
+    }
+
+    // removes the entity with all of its components, false for stale ids
+    pub fn despawn(&mut self, entity_id: EntityId) -> bool {
+        if !self.entity_id_allocator.free(entity_id) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity_id);
+        }
+        true
+    }
+
+    pub fn is_alive(&self, entity_id: EntityId) -> bool {
+        self.entity_id_allocator.is_alive(entity_id)
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity_id: EntityId, component: T) -> Option<T> {
+        if !self.is_alive(entity_id) {
+            return None;
+        }
+        self.storage_or_default_mut::<T>().insert(entity_id, component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity_id: EntityId) -> Option<T> {
+        self.storage_mut::<T>()?.remove(entity_id)
+    }
+
+    pub fn get<T: 'static>(&self, entity_id: EntityId) -> Option<&T> {
+        self.storage::<T>()?.get(entity_id)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity_id: EntityId) -> Option<&mut T> {
+        self.storage_mut::<T>()?.get_mut(entity_id)
+    }
+
+    pub fn storage<T: 'static>(&self) -> Option<&ComponentStorage<T>> {
+        self.storages.get(&TypeId::of::<T>())?.as_any().downcast_ref()
+    }
+
+    pub fn storage_mut<T: 'static>(&mut self) -> Option<&mut ComponentStorage<T>> {
+        self.storages.get_mut(&TypeId::of::<T>())?.as_any_mut().downcast_mut()
+    }
+
+    fn storage_or_default_mut<T: 'static>(&mut self) -> &mut ComponentStorage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage::<T>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    // all entities with a T component
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.storage::<T>().into_iter().flat_map(|storage| storage.iter())
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.storage_mut::<T>().into_iter().flat_map(|storage| storage.iter_mut())
+    }
+
+    // calls f for every entity having both an A and a B component
+    pub fn query_pair_mut<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(EntityId, &mut A, &mut B)) {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>(), "query_pair_mut needs two different component types");
+        // the A storage is taken out of the map while iterating, so the B storage can be borrowed mutably at the same time
+        let Some(mut a_storage) = self.storages.remove(&TypeId::of::<A>()) else { return };
+        if let Some(b_storage) = self.storage_mut::<B>() {
+            let a_components = a_storage.as_any_mut().downcast_mut::<ComponentStorage<A>>().unwrap();
+            for (entity_id, a) in a_components.iter_mut() {
+                if let Some(b) = b_storage.get_mut(entity_id) {
+                    f(entity_id, a, b);
+                }
+            }
+        }
+        self.storages.insert(TypeId::of::<A>(), a_storage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[test]
+    fn components_can_be_added_read_and_removed() {
+        let mut world = World::new();
+        let entity_id = world.spawn();
+        world.insert(entity_id, Position(1));
+
+        assert_eq!(world.get::<Position>(entity_id), Some(&Position(1)));
+        assert_eq!(world.get::<Velocity>(entity_id), None);
+        world.get_mut::<Position>(entity_id).unwrap().0 = 2;
+        assert_eq!(world.remove::<Position>(entity_id), Some(Position(2)));
+        assert_eq!(world.get::<Position>(entity_id), None);
+    }
+
+    #[test]
+    fn despawning_removes_all_components_and_invalidates_the_id() {
+        let mut world = World::new();
+        let despawned = world.spawn();
+        let kept = world.spawn();
+        world.insert(despawned, Position(1));
+        world.insert(despawned, Velocity(1));
+        world.insert(kept, Position(2));
+
+        assert!(world.despawn(despawned));
+        assert!(!world.despawn(despawned));
+        assert_eq!(world.query::<Position>().collect::<Vec<_>>(), vec![(kept, &Position(2))]);
+        assert_eq!(world.query::<Velocity>().count(), 0);
+
+        let reused = world.spawn();
+        assert_eq!(reused.index(), despawned.index());
+        assert_eq!(world.get::<Position>(reused), None);
+        assert_eq!(world.insert(despawned, Position(3)), None);
+        assert_eq!(world.get::<Position>(reused), None);
+    }
+
+    #[test]
+    fn swap_removal_keeps_the_other_components_reachable() {
+        let mut storage = ComponentStorage::default();
+        let mut world = World::new();
+        let ids: Vec<EntityId> = (0..3).map(|_| world.spawn()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            storage.insert(*id, i);
+        }
+        storage.remove(ids[0]);
+        assert_eq!(storage.get(ids[1]), Some(&1));
+        assert_eq!(storage.get(ids[2]), Some(&2));
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn pair_queries_only_visit_entities_with_both_components() {
+        let mut world = World::new();
+        let moving = world.spawn();
+        let standing = world.spawn();
+        world.insert(moving, Position(0));
+        world.insert(moving, Velocity(3));
+        world.insert(standing, Position(10));
+
+        world.query_pair_mut::<Position, Velocity>(|_, position, velocity| position.0 += velocity.0);
+
+        assert_eq!(world.get::<Position>(moving), Some(&Position(3)));
+        assert_eq!(world.get::<Position>(standing), Some(&Position(10)));
+    }
+}
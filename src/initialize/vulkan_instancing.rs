@@ -14,3 +14,13 @@ pub fn get_vulkan_instance(event_loop: &EventLoop<()>) -> Arc<Instance> {
     )
     .expect("failed to create instance");
 }
+
+
+pub fn get_headless_vulkan_instance() -> Arc<Instance> {
+    let library = vulkano::VulkanLibrary::new().expect("no local Vulkan library/DLL");
+    return Instance::new(
+        library,
+        InstanceCreateInfo::default(),
+    )
+    .expect("failed to create instance");
+}
@@ -1,22 +1,26 @@
 use egui_winit_vulkano::{egui::{self, epaint::Primitive, pos2, Area, CentralPanel, ClippedPrimitive, Context, Label, RawInput, RichText, ScrollArea, TextEdit, TextStyle, Vec2}, Gui, GuiConfig};
-use engine::{engine::Engine, scene::Scene};
+use engine::{camera_controller::{FirstPersonController, FlyController, OrbitController}, engine::Engine, scene::Scene};
 use glam::Vec3;
 use physics::physics_traits::Transform;
 use rendering::{renderer::Renderer};
 use vulkano::{format::Format, image::view::ImageView, render_pass::Subpass, single_pass_renderpass, swapchain, sync::{self, future::FenceSignalFuture, GpuFuture}, Validated, VulkanError};
-use winit::{event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}};
+use winit::{event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}};
 
 pub mod initialize;
 pub mod rendering;
 pub mod physics;
 pub mod engine;
 
-use std::{env, sync::{atomic::Ordering, Arc}};
+use std::{env, fs, path::{Path, PathBuf}, sync::{atomic::Ordering, Arc}};
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
 
-    let event_loop = EventLoop::new();
+    // usage: rust-vulkan-engine --headless <frame count> <output directory>
+    //    or: rust-vulkan-engine [--headless ...] --scene <scene file>, in any order
+    let args: Vec<String> = env::args().collect();
+    let headless_arg_index = args.iter().position(|arg| arg == "--headless");
+
     //event_loop.set_control_flow(ControlFlow::Poll);
 
     //let window = WindowBuilder::new()
@@ -26,13 +30,19 @@ fn main() {
     //    .unwrap();
 
     let mut engine = Engine::new();
-    let renderer = Renderer::new(&event_loop);
 
     let scene_1 = Arc::new(Scene::new());
     
     engine.set_active_scene(scene_1.clone());
-    let translation1 = Some(Vec3{x: 1., y: 1., z: 2.});
-    engine.add_cube_to_scene(translation1);
+    match args.iter().position(|arg| arg == "--scene").and_then(|scene_arg_index| args.get(scene_arg_index + 1)) {
+        Some(scene_path) => {
+            engine.load_scene(Path::new(scene_path)).expect("failed to load scene");
+        }
+        None => {
+            let translation1 = Some(Vec3{x: 1., y: 1., z: 2.});
+            engine.add_cube_to_scene(translation1);
+        }
+    }
     //let translation2 = Some(Vec3{x: -2., y: -1., z: 5.});
     //engine.add_cube_to_scene(translation2);
     //let translation3 = Some(Vec3{x: -4., y: 4., z: 2.});
@@ -58,9 +68,40 @@ fn main() {
     //engine.add_cube_to_scene(None);
 
 
+    if let Some(headless_arg_index) = headless_arg_index {
+        // the optional values stop at the next flag, so "--headless --scene ..." keeps the defaults
+        let mut headless_values = args[headless_arg_index + 1..].iter().take_while(|arg| !arg.starts_with("--"));
+        let frame_count = headless_values.next().and_then(|arg| arg.parse().ok()).unwrap_or(1);
+        let output_directory = PathBuf::from(headless_values.next().cloned().unwrap_or("frames".to_owned()));
+        start_headless_engine(engine, Renderer::new_headless(HEADLESS_IMAGE_EXTENT), frame_count, &output_directory);
+        return;
+    }
+
+    // f1 fly, f2 orbit around the origin, f3 first person
+    let camera_transform = engine.active_scene().camera.transform();
+    engine.set_camera_controller(Some(Box::new(FlyController::new(camera_transform))));
+
+    let event_loop = EventLoop::new();
+    let renderer = Renderer::new(&event_loop);
     start_engine(event_loop, engine, renderer);
 }
 
+const HEADLESS_IMAGE_EXTENT: [u32; 2] = [1024, 1024];
+
+fn start_headless_engine(mut engine: Engine, mut renderer: Renderer, frame_count: usize, output_directory: &Path) -> () {
+    fs::create_dir_all(output_directory).expect("failed to create the output directory");
+    for frame in 0..frame_count {
+        let frame_index = frame % renderer.frames_in_flight();
+        // exactly one simulation step per frame, so the output doesn't depend on how long rendering takes
+        engine.update(engine.fixed_step());
+        engine.work_off_event_queue(&mut renderer, frame_index);
+        renderer.render_offscreen_frame(frame_index).expect("failed to render offscreen frame");
+        let frame_path = output_directory.join(format!("frame_{:05}.png", frame));
+        renderer.save_frame_png(frame_index, &frame_path).expect("failed to save frame");
+        println!("Saved frame {} to {:?}", frame, frame_path);
+    }
+}
+
 fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Renderer) -> () {
     //init scene
     // Create egui context
@@ -73,7 +114,7 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
     let gui_subpass = Subpass::from(renderer.render_pass.clone(), 1).unwrap();
     let mut gui = Gui::new_with_subpass(
         &event_loop,
-        renderer.surface.clone(),
+        renderer.surface.clone().unwrap(),
         renderer.queue.clone(),
         gui_subpass,
         renderer.buffer_manager.gui_image_view.format(),
@@ -96,20 +137,24 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
                 
             },
             Event::MainEventsCleared => {
+                // nothing can be presented while minimized, the swapchain gets recreated once the window has a size again
+                if renderer.is_minimized() {
+                    return;
+                }
                 if window_resized || recreate_swapchain {
-                    recreate_swapchain = false;
-                    
-                    //self.renderer.recreate_swapchain(); //this recreates the framebuffers as a sideeffect
-                    if window_resized {
-                        renderer.recreate_pipeline();
+                    //this recreates the framebuffers and depth images as a sideeffect
+                    if !renderer.recreate_swapchain() {
+                        return;
                     }
+                    window_resized = false;
+                    recreate_swapchain = false;
                 }
 
                 println!("Trying to acquire swapchain image!");
 
                 let (swapchain_image_index, suboptimal, acquire_future) =
                     match swapchain::acquire_next_image(
-                        renderer.swapchain.clone(),
+                        renderer.swapchain.clone().unwrap(),
                         None,
                     ).map_err(Validated::unwrap) {
                         Ok(r) => r,
@@ -134,6 +179,7 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
 
                 acquire_future.wait(None).unwrap();
                 previous_frame_end.as_mut().unwrap().cleanup_finished();
+                engine.update_to_now();
                 engine.work_off_event_queue(&mut renderer, swapchain_image_index as usize);
                 
                 gui.immediate_ui(|gui| {
@@ -180,11 +226,11 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
                     gui_command_buffer
                 );
 
-                match future.map_err(Validated::unwrap) {
+                match future {
                     Ok(future) => {
                         previous_frame_end = Some(future.boxed());
                     }
-                    Err(VulkanError::OutOfDate) => {
+                    Err(err) if matches!(err.downcast_ref::<Validated<VulkanError>>(), Some(Validated::Error(VulkanError::OutOfDate))) => {
                         recreate_swapchain = true;
                         previous_frame_end = Some(sync::now(renderer.device.clone()).boxed());
                     }
@@ -195,7 +241,11 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
                 }
                 println!("Setting previous fence index");
             },
+            Event::DeviceEvent { event: event @ DeviceEvent::MouseMotion { .. }, .. } => {
+                engine.camera_input.handle_device_event(&event);
+            }
             Event::WindowEvent { event, .. } => {
+                engine.camera_input.handle_window_event(&event);
                 //let pass_events_to_game = !gui.update(&event); // if this returns false, then egui wont have to handle the request and we can pass it to the game
                 //if pass_events_to_game {
                     match event {
@@ -214,6 +264,18 @@ fn start_engine(event_loop: EventLoop<()>, mut engine: Engine, mut renderer: Ren
                                             engine.add_cube_to_scene(None);
                                         }
                                     },
+                                    VirtualKeyCode::F1 => {
+                                        let camera_transform = engine.active_scene().camera.transform();
+                                        engine.set_camera_controller(Some(Box::new(FlyController::new(camera_transform))));
+                                    },
+                                    VirtualKeyCode::F2 => {
+                                        let camera_translation = engine.active_scene().camera.transform().translation;
+                                        engine.set_camera_controller(Some(Box::new(OrbitController::new(Vec3::ZERO, camera_translation))));
+                                    },
+                                    VirtualKeyCode::F3 => {
+                                        let camera_transform = engine.active_scene().camera.transform();
+                                        engine.set_camera_controller(Some(Box::new(FirstPersonController::new(camera_transform))));
+                                    },
                                     _ => {}
                                 }
                             },
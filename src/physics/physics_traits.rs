@@ -1,15 +1,30 @@
-use glam::{Mat4, Quat, Vec3};
+use std::ops::Mul;
 
+use glam::{Mat3, Mat4, Quat, Vec3};
 
 
-#[derive(Debug, Default, Clone, Copy)]
+
+// left handed like the cameras: x is right, y is up and z is forward
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
 }
 
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
 impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
     pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
             translation,
@@ -18,22 +33,119 @@ impl Transform {
         }
     }
 
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    // shear (e.g. from non uniformly scaled parents of rotated children) can't be represented and is lost
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale
+        }
+    }
+
+    // scale first, then rotation, then translation
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
     pub fn model_matrix(&self) -> [[f32; 4]; 4] {
-        let rotation_matrix = Mat4::from_quat(self.rotation);
-        let scale_matrix = Mat4::from_scale(Vec3{ x: 1. , y: 1., z: 1.});
-        let translation_matrix = Mat4::from_translation( self.translation);
-        println!("TRANSLATION MATRIX: {:?}", translation_matrix);
-        let model_matrix = translation_matrix * rotation_matrix * scale_matrix;
-        println!("MODEL MATRIX {:?}", model_matrix);
-        
-        // Ensure the model_matrix is converted properly to [[f32; 4]; 4]
-        let model_array: [[f32; 4]; 4] = model_matrix.to_cols_array_2d();
-        
-        // Verify the array format
-        for row in &model_array {
-            println!("{:?}", row);
+        self.matrix().to_cols_array_2d()
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    // turns the transform so forward points at target and up is as close to the given up as possible.
+    // the rotation stays as is if target is where the transform is or straight above/below it
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = (target - self.translation).normalize_or_zero();
+        let right = up.cross(forward).normalize_or_zero();
+        if forward == Vec3::ZERO || right == Vec3::ZERO {
+            return;
+        }
+        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, forward.cross(right), forward));
+    }
+
+    pub fn looking_at(mut self, target: Vec3, up: Vec3) -> Self {
+        self.look_at(target, up);
+        self
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.translation + self.rotation * (self.scale * point)
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.rotation * (self.scale * vector)
+    }
+
+    // undoes the transform, exact for uniform scale (non uniform scale would need shear, see from_matrix)
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        let scale = self.scale.recip();
+        Self {
+            translation: scale * (rotation * -self.translation),
+            rotation,
+            scale
+        }
+    }
+
+    // blends translation and scale linearly and rotation by normalized linear interpolation (cheaper than slerp, fine for small angles), alpha 0 is self and 1 is other
+    pub fn lerp(&self, other: &Transform, alpha: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, alpha),
+            rotation: self.rotation.lerp(other.rotation, alpha),
+            scale: self.scale.lerp(other.scale, alpha)
+        }
+    }
+
+    // like lerp, but the rotation turns at constant speed
+    pub fn slerp(&self, other: &Transform, alpha: f32) -> Self {
+        Self {
+            rotation: self.rotation.slerp(other.rotation, alpha),
+            ..self.lerp(other, alpha)
         }
-        model_array
+    }
+}
+
+// parent * child is the child's transform relative to where the parent is, other gets applied first
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Self {
+            translation: self.transform_point(other.translation),
+            rotation: self.rotation * other.rotation,
+            scale: self.scale * other.scale
+        }
+    }
+}
+
+impl From<Transform> for Mat4 {
+    fn from(transform: Transform) -> Self {
+        transform.matrix()
+    }
+}
+
+impl From<Mat4> for Transform {
+    fn from(matrix: Mat4) -> Self {
+        Transform::from_matrix(matrix)
     }
 }
 
@@ -49,4 +161,87 @@ pub trait Movable {
 
 pub trait HasTransform {
     fn get_transform(&self) -> Transform;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn sample_transform() -> Transform {
+        Transform::new(Vec3::new(1., 2., 3.), Quat::from_rotation_y(0.7) * Quat::from_rotation_x(0.3), Vec3::splat(2.))
+    }
+
+    #[test]
+    fn the_default_is_the_identity() {
+        assert_eq!(Transform::default(), Transform::IDENTITY);
+        assert_eq!(Transform::default().matrix(), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn matrices_honor_scale_and_convert_back() {
+        let transform = Transform::new(Vec3::new(1., 0., 0.), Quat::from_rotation_z(FRAC_PI_2), Vec3::new(2., 3., 4.));
+        let matrix = Mat4::from(transform);
+        assert!(matrix.transform_point3(Vec3::X).abs_diff_eq(Vec3::new(1., 2., 0.), 1e-6));
+        assert!(matrix.abs_diff_eq(Mat4::from_cols_array_2d(&transform.model_matrix()), 0.));
+
+        let converted = Transform::from(matrix);
+        assert!(converted.translation.abs_diff_eq(transform.translation, 1e-6));
+        assert!(converted.rotation.abs_diff_eq(transform.rotation, 1e-6));
+        assert!(converted.scale.abs_diff_eq(transform.scale, 1e-6));
+    }
+
+    #[test]
+    fn directions_follow_the_rotation() {
+        let transform = Transform { rotation: Quat::from_rotation_y(FRAC_PI_2), ..Default::default() };
+        assert!(transform.forward().abs_diff_eq(Vec3::X, 1e-6));
+        assert!(transform.right().abs_diff_eq(-Vec3::Z, 1e-6));
+        assert!(transform.up().abs_diff_eq(Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let transform = Transform::from_translation(Vec3::new(0., 0., -5.)).looking_at(Vec3::new(3., 4., 0.), Vec3::Y);
+        assert!(transform.forward().abs_diff_eq(Vec3::new(3., 4., 5.).normalize(), 1e-6));
+        assert!(transform.right().y.abs() < 1e-6);
+        assert!(transform.up().y > 0.);
+
+        // degenerate targets keep the rotation
+        let mut unchanged = sample_transform();
+        unchanged.look_at(unchanged.translation, Vec3::Y);
+        unchanged.look_at(unchanged.translation + Vec3::Y, Vec3::Y);
+        assert_eq!(unchanged.rotation, sample_transform().rotation);
+    }
+
+    #[test]
+    fn composition_matches_matrix_multiplication() {
+        let parent = sample_transform();
+        let child = Transform::new(Vec3::new(-1., 0., 2.), Quat::from_rotation_z(1.1), Vec3::splat(0.5));
+        assert!((parent * child).matrix().abs_diff_eq(parent.matrix() * child.matrix(), 1e-5));
+        assert!(parent.transform_point(Vec3::ONE).abs_diff_eq(parent.matrix().transform_point3(Vec3::ONE), 1e-5));
+        assert!(parent.transform_vector(Vec3::ONE).abs_diff_eq(parent.matrix().transform_vector3(Vec3::ONE), 1e-5));
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let transform = sample_transform();
+        let point = Vec3::new(0.5, -2., 7.);
+        assert!(transform.inverse().transform_point(transform.transform_point(point)).abs_diff_eq(point, 1e-5));
+        assert!((transform * transform.inverse()).matrix().abs_diff_eq(Mat4::IDENTITY, 1e-5));
+        assert!(transform.inverse().matrix().abs_diff_eq(transform.matrix().inverse(), 1e-5));
+    }
+
+    #[test]
+    fn interpolation_blends_between_the_transforms() {
+        let from = Transform::IDENTITY;
+        let to = Transform::new(Vec3::new(2., 0., 0.), Quat::from_rotation_y(FRAC_PI_2), Vec3::splat(3.));
+        for halfway in [from.lerp(&to, 0.5), from.slerp(&to, 0.5)] {
+            assert!(halfway.translation.abs_diff_eq(Vec3::X, 1e-6));
+            assert!(halfway.scale.abs_diff_eq(Vec3::splat(2.), 1e-6));
+            assert!(halfway.rotation.abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2 / 2.), 1e-5));
+        }
+        assert!(from.lerp(&to, 1.).matrix().abs_diff_eq(to.matrix(), 1e-6));
+        assert!(from.slerp(&to, 0.25).rotation.abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2 / 4.), 1e-6));
+    }
+}
@@ -2,10 +2,17 @@ pub mod renderer;
 pub mod window_manager;
 pub mod shaders;
 pub mod primitives;
+pub mod procedural_meshes;
 pub mod rendering_traits;
 pub mod frame;
 pub mod buffer_manager;
-pub mod entities;
 pub mod vertex_buffers;
 pub mod mesh_accessor;
-pub mod transform_buffers;
\ No newline at end of file
+pub mod mesh_registry;
+pub mod transform_buffers;
+pub mod instance_buffers;
+pub mod free_list_allocator;
+pub mod obj_loader;
+pub mod gltf_loader;
+#[cfg(test)]
+pub mod golden_images;
\ No newline at end of file
@@ -1,15 +1,18 @@
 use std::{sync::Arc};
 
 use image::buffer;
-use vulkano::{command_buffer::{allocator::{CommandBufferAllocator, StandardCommandBufferAllocator}, AutoCommandBufferBuilder, BufferCopy, CommandBufferUsage, CopyBufferInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents, SubpassEndInfo}, device::Device, image::{view::ImageView, Image}, pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}, NonExhaustive, ValidationError};
+use vulkano::{command_buffer::{allocator::{CommandBufferAllocator, StandardCommandBufferAllocator}, AutoCommandBufferBuilder, BufferCopy, CommandBufferUsage, CopyBufferInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents, SubpassEndInfo}, device::Device, format::Format, image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator}, pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}, NonExhaustive, ValidationError};
 use winit::window::Window;
 
 use super::{buffer_manager::BufferManager, mesh_accessor};
 
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
 pub struct Frame {
     pub swapchain_image: Arc<Image>,
     pub swapchain_image_view: Arc<ImageView>,
     swapchain_image_index: usize,
+    pub depth_image_view: Option<Arc<ImageView>>,
     pub framebuffer: Option<Arc<Framebuffer>>,
     pub draw_command_buffer: Option<Arc<PrimaryAutoCommandBuffer>>,
 }
@@ -21,20 +24,40 @@ impl Frame {
             swapchain_image,    
             swapchain_image_view,
             swapchain_image_index,
+            depth_image_view: None,
             framebuffer: None,
             draw_command_buffer: None,
         }
     }
 
-    pub fn init_framebuffer(&mut self, render_pass: Arc<RenderPass>) -> () {
+    // the depth image is sized after the swapchain image, so this has to be called again whenever the swapchain gets recreated
+    pub fn init_framebuffer(&mut self, render_pass: Arc<RenderPass>, memory_allocator: Arc<StandardMemoryAllocator>) -> () {
         let view = ImageView::new_default(self.swapchain_image.clone()).unwrap();
+        let depth_image_view = Frame::build_depth_image_view(memory_allocator, self.swapchain_image.extent());
         let framebuffer = Framebuffer::new(
             render_pass,
             FramebufferCreateInfo {
-                attachments: vec![view],
+                attachments: vec![view, depth_image_view.clone()],
                 ..Default::default()
             })
             .unwrap();
+        self.depth_image_view = Some(depth_image_view);
         self.framebuffer = Some(framebuffer);
     }
+
+    fn build_depth_image_view(memory_allocator: Arc<StandardMemoryAllocator>, extent: [u32; 3]) -> Arc<ImageView> {
+        let depth_image = Image::new(
+            memory_allocator,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: DEPTH_FORMAT,
+                extent,
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        ImageView::new_default(depth_image).unwrap()
+    }
 }
\ No newline at end of file
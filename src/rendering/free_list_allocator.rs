@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+// hands out ranges of a buffer with a fixed capacity (in elements, e.g. vertices), freed ranges get merged with their free neighbours
+#[derive(Debug, Clone)]
+pub struct FreeListAllocator {
+    capacity: usize,
+    // sorted by start, never overlapping or adjacent
+    free_ranges: Vec<Range<usize>>,
+}
+
+impl FreeListAllocator {
+    pub fn new(capacity: usize) -> Self {
+        let free_ranges = match capacity {
+            0 => Vec::new(),
+            // one free range spanning the whole buffer
+            _ => vec![Range { start: 0, end: capacity }],
+        };
+        Self {
+            capacity,
+            free_ranges
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn used(&self) -> usize {
+        self.capacity - self.free_ranges.iter().map(|free_range| free_range.len()).sum::<usize>()
+    }
+
+    pub fn free_ranges(&self) -> &[Range<usize>] {
+        &self.free_ranges
+    }
+
+    // first fit, returns the offset of the allocated range or None if no free range is big enough
+    pub fn allocate(&mut self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return Some(0);
+        }
+        let free_range_position = self.free_ranges.iter().position(|free_range| free_range.len() >= len)?;
+        let free_range = &mut self.free_ranges[free_range_position];
+        let offset = free_range.start;
+        free_range.start += len;
+        if free_range.start == free_range.end {
+            self.free_ranges.remove(free_range_position);
+        }
+        Some(offset)
+    }
+
+    pub fn free(&mut self, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let freed_range = offset..offset + len;
+        assert!(freed_range.end <= self.capacity, "freed range {:?} is out of bounds", freed_range);
+        let insert_position = self.free_ranges.partition_point(|free_range| free_range.start < freed_range.start);
+        let overlaps_previous = insert_position > 0 && self.free_ranges[insert_position - 1].end > freed_range.start;
+        let overlaps_next = insert_position < self.free_ranges.len() && self.free_ranges[insert_position].start < freed_range.end;
+        assert!(!overlaps_previous && !overlaps_next, "range {:?} was freed twice", freed_range);
+
+        self.free_ranges.insert(insert_position, freed_range);
+        // merge with the next and then the previous free range
+        if insert_position + 1 < self.free_ranges.len() && self.free_ranges[insert_position].end == self.free_ranges[insert_position + 1].start {
+            let next_range = self.free_ranges.remove(insert_position + 1);
+            self.free_ranges[insert_position].end = next_range.end;
+        }
+        if insert_position > 0 && self.free_ranges[insert_position - 1].end == self.free_ranges[insert_position].start {
+            let merged_range = self.free_ranges.remove(insert_position);
+            self.free_ranges[insert_position - 1].end = merged_range.end;
+        }
+    }
+
+    // the capacity needed so that len more elements fit, reusing a free range at the end of the buffer
+    pub fn required_capacity(&self, len: usize) -> usize {
+        let free_tail = match self.free_ranges.last() {
+            Some(free_range) if free_range.end == self.capacity => free_range.len(),
+            _ => 0,
+        };
+        self.capacity + len.saturating_sub(free_tail)
+    }
+
+    // the new space at the end becomes free, allocated ranges keep their offsets
+    pub fn grow(&mut self, new_capacity: usize) {
+        assert!(new_capacity >= self.capacity, "allocators can't shrink");
+        if new_capacity == self.capacity {
+            return;
+        }
+        let old_capacity = self.capacity;
+        self.capacity = new_capacity;
+        self.free(old_capacity, new_capacity - old_capacity);
+    }
+}
+
+// doubles the capacity until the required number of elements fits
+pub fn grown_capacity(capacity: usize, required_capacity: usize) -> usize {
+    let mut grown_capacity = capacity.max(1);
+    while grown_capacity < required_capacity {
+        grown_capacity *= 2;
+    }
+    grown_capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_first_fit() {
+        let mut allocator = FreeListAllocator::new(10);
+        assert_eq!(allocator.allocate(4), Some(0));
+        assert_eq!(allocator.allocate(4), Some(4));
+        assert_eq!(allocator.allocate(4), None);
+        assert_eq!(allocator.allocate(2), Some(8));
+        assert_eq!(allocator.used(), 10);
+    }
+
+    #[test]
+    fn freed_ranges_are_reused_and_merged() {
+        let mut allocator = FreeListAllocator::new(12);
+        let a = allocator.allocate(4).unwrap();
+        let b = allocator.allocate(4).unwrap();
+        let c = allocator.allocate(4).unwrap();
+
+        allocator.free(a, 4);
+        allocator.free(c, 4);
+        assert_eq!(allocator.free_ranges(), &[0..4, 8..12]);
+        assert_eq!(allocator.allocate(6), None);
+        allocator.free(b, 4);
+        assert_eq!(allocator.free_ranges(), &[Range { start: 0, end: 12 }]);
+        assert_eq!(allocator.allocate(12), Some(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn freeing_twice_panics() {
+        let mut allocator = FreeListAllocator::new(8);
+        let offset = allocator.allocate(4).unwrap();
+        allocator.free(offset, 4);
+        allocator.free(offset, 4);
+    }
+
+    #[test]
+    fn growing_extends_a_free_tail() {
+        let mut allocator = FreeListAllocator::new(8);
+        allocator.allocate(6).unwrap();
+        assert_eq!(allocator.allocate(4), None);
+        // the 2 free elements at the end are reused
+        assert_eq!(allocator.required_capacity(4), 10);
+
+        allocator.grow(grown_capacity(allocator.capacity(), allocator.required_capacity(4)));
+        assert_eq!(allocator.capacity(), 16);
+        assert_eq!(allocator.free_ranges(), &[Range { start: 6, end: 16 }]);
+        assert_eq!(allocator.allocate(4), Some(6));
+    }
+
+    #[test]
+    fn capacity_doubles_until_the_required_elements_fit() {
+        assert_eq!(grown_capacity(1024, 1025), 2048);
+        assert_eq!(grown_capacity(1024, 100_000), 131_072);
+        assert_eq!(grown_capacity(1024, 1024), 1024);
+        assert_eq!(grown_capacity(0, 3), 4);
+    }
+}
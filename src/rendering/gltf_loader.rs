@@ -0,0 +1,237 @@
+use std::{error::Error, path::Path};
+
+use glam::{Mat4, Vec4};
+use gltf::{buffer, camera::Projection, mesh::Mode, Document};
+
+use crate::{engine::camera::Camera, physics::physics_traits::Transform};
+
+use super::primitives::{Mesh, Vertex};
+
+// gltf is right handed, the engine left handed with +z forward (see Mesh::mirror_z). mirroring z converts between them, the gltf camera's -z view direction becomes +z
+const MIRROR_Z: Mat4 = Mat4::from_diagonal(Vec4::new(1., 1., -1., 1.));
+
+// default aspect ratio for gltf cameras that don't specify one, same as the default scene camera
+const DEFAULT_CAMERA_ASPECT_RATIO: f32 = 16. / 9.;
+
+pub struct GltfNode {
+    pub name: String,
+    // relative to the parent node
+    pub transform: Transform,
+    // index into GltfImport::nodes, parents come before their children
+    pub parent: Option<usize>,
+    // one mesh per primitive
+    pub meshes: Vec<Mesh>,
+}
+
+// only triangles are imported, lines and points are left out
+pub struct SkippedPrimitive {
+    // "<file name>/<mesh name>/<primitive index>", like the mesh it would have become
+    pub name: String,
+    pub mode: Mode,
+}
+
+pub struct GltfImport {
+    pub nodes: Vec<GltfNode>,
+    pub cameras: Vec<Camera>,
+    pub skipped_primitives: Vec<SkippedPrimitive>,
+}
+
+impl GltfImport {
+    pub fn world_matrix(&self, node_index: usize) -> Mat4 {
+        let node = &self.nodes[node_index];
+        let local_matrix = Mat4::from_scale_rotation_translation(node.transform.scale, node.transform.rotation, node.transform.translation);
+        match node.parent {
+            Some(parent_index) => self.world_matrix(parent_index) * local_matrix,
+            None => local_matrix,
+        }
+    }
+}
+
+pub fn load_gltf(path: &Path) -> Result<GltfImport, Box<dyn Error>> {
+    let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("gltf");
+    let (document, buffers, _images) = gltf::import(path)?;
+    Ok(import_document(&document, &buffers, name))
+}
+
+// .gltf (with embedded buffers) or .glb contents
+pub fn parse_gltf(bytes: &[u8], name: &str) -> Result<GltfImport, Box<dyn Error>> {
+    let (document, buffers, _images) = gltf::import_slice(bytes)?;
+    Ok(import_document(&document, &buffers, name))
+}
+
+fn import_document(document: &Document, buffers: &[buffer::Data], name: &str) -> GltfImport {
+    let mut import = GltfImport {
+        nodes: Vec::new(),
+        cameras: Vec::new(),
+        skipped_primitives: Vec::new(),
+    };
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            import_node(&node, None, Mat4::IDENTITY, buffers, name, &mut import);
+        }
+    }
+    import
+}
+
+fn to_left_handed(matrix: Mat4) -> Mat4 {
+    MIRROR_Z * matrix * MIRROR_Z
+}
+
+// walks the node tree, the nodes keep their local transforms, cameras get the world transform of their node
+fn import_node(node: &gltf::Node, parent: Option<usize>, parent_world_matrix: Mat4, buffers: &[buffer::Data], name: &str, import: &mut GltfImport) {
+    let local_matrix = to_left_handed(Mat4::from_cols_array_2d(&node.transform().matrix()));
+    let world_matrix = parent_world_matrix * local_matrix;
+
+    let mut meshes = Vec::new();
+    if let Some(mesh) = node.mesh() {
+        let mesh_name = mesh.name().map(str::to_owned).unwrap_or(mesh.index().to_string());
+        for primitive in mesh.primitives() {
+            let primitive_name = format!("{}/{}/{}", name, mesh_name, primitive.index());
+            if primitive.mode() != Mode::Triangles {
+                import.skipped_primitives.push(SkippedPrimitive { name: primitive_name, mode: primitive.mode() });
+                continue;
+            }
+            meshes.extend(primitive_to_mesh(&primitive, buffers, primitive_name));
+        }
+    }
+    let node_index = import.nodes.len();
+    import.nodes.push(GltfNode {
+        name: node.name().map(str::to_owned).unwrap_or(node.index().to_string()),
+        transform: Transform::from_matrix(local_matrix),
+        parent,
+        meshes,
+    });
+
+    if let Some(camera) = node.camera() {
+        let projection_matrix = match camera.projection() {
+            Projection::Perspective(perspective) => {
+                let aspect_ratio = perspective.aspect_ratio().unwrap_or(DEFAULT_CAMERA_ASPECT_RATIO);
+                match perspective.zfar() {
+                    Some(zfar) => Mat4::perspective_lh(perspective.yfov(), aspect_ratio, perspective.znear(), zfar),
+                    None => Mat4::perspective_infinite_lh(perspective.yfov(), aspect_ratio, perspective.znear()),
+                }
+            }
+            Projection::Orthographic(orthographic) => Mat4::orthographic_lh(
+                -orthographic.xmag(), orthographic.xmag(), -orthographic.ymag(), orthographic.ymag(), orthographic.znear(), orthographic.zfar()
+            ),
+        };
+        import.cameras.push(Camera::new(Transform::from_matrix(world_matrix), projection_matrix));
+    }
+
+    for child in node.children() {
+        import_node(&child, Some(node_index), world_matrix, buffers, name, import);
+    }
+}
+
+// there is no material system yet, so the material's base color factor gets baked into the vertex colors (textures are ignored)
+fn primitive_to_mesh(primitive: &gltf::Primitive, buffers: &[buffer::Data], name: String) -> Option<Mesh> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+    let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(|tangents| tangents.collect());
+    let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect());
+    let colors: Option<Vec<[f32; 4]>> = reader.read_colors(0).map(|colors| colors.into_rgba_f32().collect());
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let base_color = Vec4::from(primitive.material().pbr_metallic_roughness().base_color_factor());
+
+    let data = positions.iter().enumerate().map(|(i, position)| {
+        let vertex_color = colors.as_ref().map(|colors| Vec4::from(colors[i])).unwrap_or(Vec4::ONE);
+        Vertex {
+            position: *position,
+            normal: normals.as_ref().map(|normals| normals[i]).unwrap_or_default(),
+            uv: uvs.as_ref().map(|uvs| uvs[i]).unwrap_or_default(),
+            tangent: tangents.as_ref().map(|tangents| tangents[i]).unwrap_or_default(),
+            color: (vertex_color * base_color).to_array(),
+        }
+    })
+    .collect();
+
+    let mut mesh = Mesh::new(data, indices, name);
+    mesh.mirror_z();
+    if normals.is_none() {
+        mesh.recalculate_normals();
+    }
+    if tangents.is_none() {
+        mesh.recalculate_tangents();
+    }
+    Some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use glam::Vec3;
+
+    use crate::physics::physics_traits::HasTransform;
+
+    use super::*;
+
+    fn sample_path(file_name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("gltf").join(file_name)
+    }
+
+    #[test]
+    fn nodes_keep_their_local_transform_and_parent() {
+        let import = load_gltf(&sample_path("triangle_hierarchy.gltf")).unwrap();
+        let node_names: Vec<&str> = import.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(node_names, vec!["root", "child", "camera", "sibling"]);
+        assert_eq!(import.nodes.iter().map(|node| node.parent).collect::<Vec<_>>(), vec![None, Some(0), Some(0), None]);
+        assert_eq!(import.nodes.iter().map(|node| node.meshes.len()).collect::<Vec<_>>(), vec![0, 1, 0, 1]);
+
+        let child = &import.nodes[1];
+        assert!(child.transform.translation.abs_diff_eq(Vec3::new(0., 2., 0.), 1e-6));
+        assert!(child.transform.scale.abs_diff_eq(Vec3::splat(2.), 1e-6));
+        let child_world_transform = Transform::from_matrix(import.world_matrix(1));
+        assert!(child_world_transform.translation.abs_diff_eq(Vec3::new(1., 2., 0.), 1e-6));
+        assert!(child_world_transform.scale.abs_diff_eq(Vec3::splat(2.), 1e-6));
+
+        let sibling_world_transform = Transform::from_matrix(import.world_matrix(3));
+        assert!(sibling_world_transform.translation.abs_diff_eq(Vec3::new(-1., 0., 0.), 1e-6));
+    }
+
+    #[test]
+    fn primitives_become_indexed_meshes_with_material_color() {
+        let import = load_gltf(&sample_path("triangle_hierarchy.gltf")).unwrap();
+        let mesh = &import.nodes[1].meshes[0];
+        assert_eq!(mesh.name, "triangle_hierarchy/triangle/0");
+        assert_eq!(mesh.data.len(), 3);
+        assert_eq!(mesh.indices, vec![0, 2, 1]);
+        assert!(mesh.data.iter().all(|vertex| vertex.color == [0., 1., 0., 1.]));
+        // the triangle faces the camera in both coordinate systems
+        assert!(mesh.data.iter().all(|vertex| Vec3::from(vertex.normal).abs_diff_eq(-Vec3::Z, 1e-6)));
+    }
+
+    #[test]
+    fn primitives_that_are_no_triangles_are_skipped() {
+        let gltf = std::fs::read_to_string(sample_path("triangle_hierarchy.gltf")).unwrap().replace("\"indices\": 1,", "\"indices\": 1, \"mode\": 0,");
+        let import = parse_gltf(gltf.as_bytes(), "points").unwrap();
+        assert!(import.nodes.iter().all(|node| node.meshes.is_empty()));
+        let skipped_primitives: Vec<(&str, Mode)> = import.skipped_primitives.iter().map(|skipped_primitive| (skipped_primitive.name.as_str(), skipped_primitive.mode)).collect();
+        // both nodes use the mesh, it is skipped for each of them
+        assert_eq!(skipped_primitives, vec![("points/triangle/0", Mode::Points); 2]);
+    }
+
+    #[test]
+    fn camera_nodes_become_cameras() {
+        let import = load_gltf(&sample_path("triangle_hierarchy.gltf")).unwrap();
+        assert_eq!(import.cameras.len(), 1);
+        let camera = &import.cameras[0];
+        assert!(camera.get_transform().translation.abs_diff_eq(Vec3::new(1., 0., -5.), 1e-6));
+        assert!(camera.get_transform().forward().abs_diff_eq(Vec3::Z, 1e-6));
+        // left handed projections map the view direction to positive w
+        assert_eq!(camera.projection_matrix().z_axis.w, 1.);
+    }
+
+    #[test]
+    fn right_handed_transforms_are_mirrored_to_left_handed() {
+        let right_handed = Mat4::from_scale_rotation_translation(Vec3::new(1., 2., 3.), glam::Quat::from_rotation_y(0.7) * glam::Quat::from_rotation_x(0.3), Vec3::new(1., 2., 3.));
+        let point = Vec3::new(0.5, -1., 2.);
+        let mirror = |point: Vec3| Vec3::new(point.x, point.y, -point.z);
+        let left_handed_point = to_left_handed(right_handed).transform_point3(mirror(point));
+        assert!(left_handed_point.abs_diff_eq(mirror(right_handed.transform_point3(point)), 1e-5));
+    }
+}
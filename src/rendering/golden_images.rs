@@ -0,0 +1,202 @@
+use std::{env, fs, path::PathBuf, sync::Arc};
+
+use glam::Vec3;
+use image::{Rgba, RgbaImage};
+
+use crate::engine::{engine::Engine, scene::Scene};
+
+use super::renderer::Renderer;
+
+// set this env var to (re)write the golden images from the current render output instead of comparing against them
+pub const UPDATE_GOLDEN_IMAGES_ENV_VAR: &str = "UPDATE_GOLDEN_IMAGES";
+const GOLDEN_IMAGE_EXTENT: [u32; 2] = [256, 256];
+// max per channel difference before a pixel counts as mismatched, leaves some room for rasterization differences between drivers
+const DEFAULT_CHANNEL_TOLERANCE: u8 = 2;
+
+pub struct ReferenceScene {
+    pub name: &'static str,
+    pub cube_translations: Vec<Vec3>,
+}
+
+pub fn reference_scenes() -> Vec<ReferenceScene> {
+    vec![
+        ReferenceScene {
+            name: "single_cube",
+            cube_translations: vec![Vec3 { x: 0., y: 0., z: 0. }],
+        },
+        ReferenceScene {
+            name: "three_cubes",
+            cube_translations: vec![
+                Vec3 { x: -1., y: 0., z: 0. },
+                Vec3 { x: 0., y: 1., z: 1. },
+                Vec3 { x: 1., y: -0.5, z: 2. },
+            ],
+        },
+        ReferenceScene {
+            name: "overlapping_cubes",
+            cube_translations: vec![
+                Vec3 { x: 0., y: 0., z: 1. },
+                Vec3 { x: 0.1, y: 0.1, z: 0.9 },
+            ],
+        },
+    ]
+}
+
+pub struct ImageComparison {
+    pub mismatched_pixels: usize,
+    pub max_channel_difference: u8,
+    // mismatched pixels are red, matching ones are a dimmed grayscale copy of the expected image
+    pub diff_image: RgbaImage,
+}
+
+impl ImageComparison {
+    pub fn matches(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
+
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, channel_tolerance: u8) -> Result<ImageComparison, String> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(format!("image dimensions differ, actual: {:?}, expected: {:?}", actual.dimensions(), expected.dimensions()));
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+    let mut diff_image = RgbaImage::new(actual.width(), actual.height());
+    for ((actual_pixel, expected_pixel), diff_pixel) in actual.pixels().zip(expected.pixels()).zip(diff_image.pixels_mut()) {
+        let pixel_difference = actual_pixel.0.iter()
+            .zip(expected_pixel.0.iter())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap();
+        max_channel_difference = max_channel_difference.max(pixel_difference);
+        if pixel_difference > channel_tolerance {
+            mismatched_pixels += 1;
+            *diff_pixel = Rgba([255, 0, 0, 255]);
+        } else {
+            let [r, g, b, _] = expected_pixel.0;
+            let luminance = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8;
+            *diff_pixel = Rgba([luminance, luminance, luminance, 255]);
+        }
+    }
+
+    Ok(ImageComparison {
+        mismatched_pixels,
+        max_channel_difference,
+        diff_image
+    })
+}
+
+pub fn golden_image_path(scene_name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{scene_name}.png"))
+}
+
+fn diff_output_directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("golden-diffs")
+}
+
+// the golden tests need a vulkan driver with at least one device (a software one like lavapipe is enough)
+pub fn headless_rendering_available() -> bool {
+    let Ok(library) = vulkano::VulkanLibrary::new() else { return false };
+    let Ok(instance) = vulkano::instance::Instance::new(library, Default::default()) else { return false };
+    instance.enumerate_physical_devices().map(|mut devices| devices.next().is_some()).unwrap_or(false)
+}
+
+pub fn render_reference_scene(reference_scene: &ReferenceScene) -> RgbaImage {
+    let mut engine = Engine::new();
+    let mut renderer = Renderer::new_headless(GOLDEN_IMAGE_EXTENT);
+    engine.set_active_scene(Arc::new(Scene::new()));
+    for translation in reference_scene.cube_translations.iter() {
+        engine.add_cube_to_scene(Some(*translation));
+    }
+
+    let frame_index = 0;
+    engine.work_off_event_queue(&mut renderer, frame_index);
+    renderer.render_offscreen_frame(frame_index).expect("failed to render reference scene");
+    renderer.capture_frame(frame_index).expect("failed to capture reference scene")
+}
+
+// renders the scene and compares it with its golden image, panics with the paths of the written diff images on mismatch
+pub fn assert_matches_golden_image(reference_scene: &ReferenceScene) {
+    let actual = render_reference_scene(reference_scene);
+    let golden_path = golden_image_path(reference_scene.name);
+
+    if env::var_os(UPDATE_GOLDEN_IMAGES_ENV_VAR).is_some() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        actual.save(&golden_path).unwrap();
+        println!("Updated golden image {:?}", golden_path);
+        return;
+    }
+
+    let expected = match image::open(&golden_path) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(err) => panic!("could not load golden image {:?} ({err}), run the tests with {UPDATE_GOLDEN_IMAGES_ENV_VAR}=1 to create it", golden_path),
+    };
+
+    let comparison = compare_images(&actual, &expected, DEFAULT_CHANNEL_TOLERANCE).unwrap();
+    if !comparison.matches() {
+        let output_directory = diff_output_directory();
+        fs::create_dir_all(&output_directory).unwrap();
+        let actual_path = output_directory.join(format!("{}_actual.png", reference_scene.name));
+        let diff_path = output_directory.join(format!("{}_diff.png", reference_scene.name));
+        actual.save(&actual_path).unwrap();
+        comparison.diff_image.save(&diff_path).unwrap();
+        panic!(
+            "scene {} differs from its golden image in {} pixels (max channel difference {}), see {:?} and {:?}",
+            reference_scene.name, comparison.mismatched_pixels, comparison.max_channel_difference, actual_path, diff_path
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled_image(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(4, 4, Rgba(color))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = filled_image([10, 20, 30, 255]);
+        let comparison = compare_images(&image, &image, 0).unwrap();
+        assert!(comparison.matches());
+        assert_eq!(comparison.max_channel_difference, 0);
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let comparison = compare_images(&filled_image([10, 20, 30, 255]), &filled_image([12, 19, 30, 255]), 2).unwrap();
+        assert!(comparison.matches());
+        assert_eq!(comparison.max_channel_difference, 2);
+    }
+
+    #[test]
+    fn differences_above_tolerance_are_marked_in_diff_image() {
+        let expected = filled_image([0, 0, 0, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, Rgba([0, 200, 0, 255]));
+
+        let comparison = compare_images(&actual, &expected, 2).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 1);
+        assert_eq!(comparison.max_channel_difference, 200);
+        assert_eq!(*comparison.diff_image.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+        assert_eq!(*comparison.diff_image.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn different_dimensions_are_an_error() {
+        let result = compare_images(&RgbaImage::new(2, 2), &RgbaImage::new(3, 2), 0);
+        assert!(result.is_err());
+    }
+
+    // opt in with `cargo test -- --ignored`, a missing vulkan device or golden image is then a failure rather than a skip
+    #[test]
+    #[ignore = "needs a vulkan device, run with --ignored"]
+    fn reference_scenes_match_golden_images() {
+        assert!(headless_rendering_available(), "the golden image tests need a vulkan device (a software one like lavapipe is enough)");
+        for reference_scene in reference_scenes().iter() {
+            assert_matches_golden_image(reference_scene);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{error::Error, ops::Index, sync::Arc};
+
+use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}};
+
+use super::free_list_allocator::grown_capacity;
+
+// one instance -> transform slot table per frame in flight, the vertex shader looks up its model matrix through it with gl_InstanceIndex.
+// the table is rewritten every recorded frame, it is laid out mesh by mesh so each mesh's instances are one contiguous range
+pub struct InstanceBuffers {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    instance_buffers: Vec<Subbuffer<[u32]>>,
+}
+
+const INITIAL_INSTANCE_BUFFER_CAPACITY: usize = 2_i32.pow(10) as usize;
+
+impl InstanceBuffers {
+    pub fn new(memory_allocator: Arc<StandardMemoryAllocator>, swapchain_images_length: usize) -> Self {
+        let instance_buffers = (0..swapchain_images_length)
+            .map(|_| Self::allocate_instance_buffer(memory_allocator.clone(), INITIAL_INSTANCE_BUFFER_CAPACITY))
+            .collect();
+        Self {
+            memory_allocator,
+            instance_buffers
+        }
+    }
+
+    fn allocate_instance_buffer(memory_allocator: Arc<StandardMemoryAllocator>, capacity: usize) -> Subbuffer<[u32]> {
+        Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            capacity as u64
+        )
+        .unwrap()
+    }
+
+    // e.g. when a recreated swapchain has a different number of images
+    pub fn set_frame_count(&mut self, frame_count: usize) {
+        self.instance_buffers.truncate(frame_count);
+        while self.instance_buffers.len() < frame_count {
+            self.instance_buffers.push(Self::allocate_instance_buffer(self.memory_allocator.clone(), INITIAL_INSTANCE_BUFFER_CAPACITY));
+        }
+    }
+
+    // the frame must not be in use by the gpu anymore, a buffer that is too small gets replaced
+    pub fn write_instance_table(&mut self, swapchain_image_index: usize, transform_indexes: &[u32]) -> Result<(), Box<dyn Error>> {
+        let capacity = self.instance_buffers[swapchain_image_index].len() as usize;
+        if capacity < transform_indexes.len() {
+            let capacity = grown_capacity(capacity, transform_indexes.len());
+            self.instance_buffers[swapchain_image_index] = Self::allocate_instance_buffer(self.memory_allocator.clone(), capacity);
+        }
+        if transform_indexes.is_empty() {
+            return Ok(());
+        }
+        self.instance_buffers[swapchain_image_index].write()?[..transform_indexes.len()].copy_from_slice(transform_indexes);
+        Ok(())
+    }
+}
+
+impl Index<usize> for InstanceBuffers {
+    type Output = Subbuffer<[u32]>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.instance_buffers[index]
+    }
+}
@@ -1,17 +1,37 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
-use super::primitives::Mesh;
+use crate::engine::entity_id::{EntityId, EntityMap};
+
+use super::{primitives::Mesh, rendering_traits::Visibility};
 
 #[derive(Debug, Clone, Default)]
 pub struct MeshAccessor {
     pub meshes: Vec<Mesh>,
     pub mesh_name_instance_count_map: HashMap<String, usize>,
     pub mesh_name_first_vertex_index_map: HashMap<String, usize>,
+    pub mesh_name_first_index_map: HashMap<String, usize>,
+    pub entity_mesh_name_map: EntityMap<String>,
+    // compacted list of the visible entities per mesh, only these get drawn
+    pub mesh_name_visible_entities_map: HashMap<String, Vec<EntityId>>,
+    // position of each visible entity in its mesh's visible entity list
+    entity_visible_position_map: EntityMap<usize>,
+}
+
+// where a mesh lives in the vertex and index buffer, in vertices and indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshLocation {
+    pub first_vertex_index: usize,
+    pub vertex_count: usize,
+    pub first_index: usize,
+    pub index_count: usize,
 }
 
-pub enum MeshAccessorAddEntityResult {
-    AppendedToExistingMesh,
-    CreatedNewMesh(Mesh)
+// instance -> transform slot table of one frame, with the range of instances each mesh draws
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstanceTable {
+    pub transform_indexes: Vec<u32>,
+    // in the order of MeshAccessor::meshes, meshes without visible entities are left out
+    pub mesh_instance_ranges: Vec<(String, Range<u32>)>,
 }
 
 impl MeshAccessor {
@@ -19,36 +39,276 @@ impl MeshAccessor {
         let meshes = Vec::new();
         let mesh_name_instance_count_map = HashMap::new();
         let mesh_name_first_vertex_index_map = HashMap::new();
+        let mesh_name_first_index_map = HashMap::new();
+        let entity_mesh_name_map = EntityMap::new();
+        let mesh_name_visible_entities_map = HashMap::new();
+        let entity_visible_position_map = EntityMap::new();
         Self {
             meshes,
             mesh_name_instance_count_map,
-            mesh_name_first_vertex_index_map
+            mesh_name_first_vertex_index_map,
+            mesh_name_first_index_map,
+            entity_mesh_name_map,
+            mesh_name_visible_entities_map,
+            entity_visible_position_map
         }
     }
 
-    pub fn add_entity(&mut self, entity_mesh: Mesh) -> MeshAccessorAddEntityResult {
-        match self.mesh_name_instance_count_map.contains_key(entity_mesh.get_name()) {
-            true => {
-                *self.mesh_name_instance_count_map.get_mut(entity_mesh.get_name()).unwrap() += 1;
-                return MeshAccessorAddEntityResult::AppendedToExistingMesh;
-            },
-            false => { 
-                self.add_new_mesh(entity_mesh.clone());
-                return MeshAccessorAddEntityResult::CreatedNewMesh(entity_mesh);
-            }
+    pub fn contains_mesh(&self, mesh_name: &str) -> bool {
+        self.mesh_name_instance_count_map.contains_key(mesh_name)
+    }
+
+    // the caller has allocated the mesh's ranges in the vertex and index buffer, the mesh starts out without instances
+    pub fn add_mesh(&mut self, mesh: Mesh, first_vertex_index: usize, first_index: usize) {
+        let mesh_name = mesh.get_name().to_string();
+        self.mesh_name_instance_count_map.insert(mesh_name.clone(), 0);
+        self.mesh_name_first_vertex_index_map.insert(mesh_name.clone(), first_vertex_index);
+        self.mesh_name_first_index_map.insert(mesh_name, first_index);
+        self.meshes.push(mesh);
+    }
+
+    // entities start out visible, returns false if the mesh wasn't added before
+    pub fn add_entity(&mut self, mesh_name: &str, entity_id: EntityId) -> bool {
+        let Some(instance_count) = self.mesh_name_instance_count_map.get_mut(mesh_name) else { return false };
+        *instance_count += 1;
+        self.entity_mesh_name_map.insert(entity_id, mesh_name.to_owned());
+        self.show_entity(entity_id, mesh_name);
+        true
+    }
+
+    // returns the name of the entity's mesh, the mesh itself is kept even when its instance count drops to 0
+    pub fn remove_entity(&mut self, entity_id: EntityId) -> Option<String> {
+        self.hide_entity(entity_id);
+        let mesh_name = self.entity_mesh_name_map.remove(entity_id)?;
+        let instance_count = self.mesh_name_instance_count_map.get_mut(&mesh_name)?;
+        *instance_count -= 1;
+        Some(mesh_name)
+    }
+
+    // returns false if the entity has no mesh
+    pub fn set_entity_visibility(&mut self, entity_id: EntityId, visibility: Visibility) -> bool {
+        let Some(mesh_name) = self.entity_mesh_name_map.get(entity_id).cloned() else { return false };
+        match visibility {
+            Visibility::Visible => self.show_entity(entity_id, &mesh_name),
+            Visibility::Invisible => self.hide_entity(entity_id),
+        }
+        true
+    }
+
+    pub fn is_entity_visible(&self, entity_id: EntityId) -> bool {
+        self.entity_visible_position_map.contains(entity_id)
+    }
+
+    pub fn visible_entities(&self, mesh_name: &str) -> &[EntityId] {
+        self.mesh_name_visible_entities_map.get(mesh_name).map(|visible_entities| visible_entities.as_slice()).unwrap_or(&[])
+    }
+
+    fn show_entity(&mut self, entity_id: EntityId, mesh_name: &str) {
+        if self.is_entity_visible(entity_id) {
+            return;
+        }
+        let visible_entities = self.mesh_name_visible_entities_map.entry(mesh_name.to_owned()).or_default();
+        self.entity_visible_position_map.insert(entity_id, visible_entities.len());
+        visible_entities.push(entity_id);
+    }
+
+    // swap-remove, keeps the visible entity list compacted
+    fn hide_entity(&mut self, entity_id: EntityId) {
+        let Some(visible_position) = self.entity_visible_position_map.remove(entity_id) else { return };
+        let visible_entities = self.mesh_name_visible_entities_map.get_mut(self.entity_mesh_name_map.get(entity_id).unwrap()).unwrap();
+        visible_entities.swap_remove(visible_position);
+        if let Some(moved_entity_id) = visible_entities.get(visible_position) {
+            self.entity_visible_position_map.insert(*moved_entity_id, visible_position);
         }
     }
 
-    fn add_new_mesh(&mut self, entity_mesh: Mesh) {
-        let mesh_name = entity_mesh.get_name().to_string();
-        self.mesh_name_instance_count_map.insert(mesh_name.clone(), 1usize);
-        self.mesh_name_first_vertex_index_map.insert(mesh_name, self.get_last_vertex_index());
-        self.meshes.push(entity_mesh);
+    // every mesh's visible entities get one contiguous range of instances, no matter where their transforms live in the transform buffer.
+    // entities without a transform slot are skipped
+    pub fn build_instance_table(&self, transform_index: impl Fn(EntityId) -> Option<usize>) -> InstanceTable {
+        let mut instance_table = InstanceTable::default();
+        for mesh in self.meshes.iter() {
+            let first_instance = instance_table.transform_indexes.len() as u32;
+            instance_table.transform_indexes.extend(self.visible_entities(mesh.get_name()).iter()
+                .filter_map(|entity_id| transform_index(*entity_id))
+                .map(|entity_transform_index| entity_transform_index as u32));
+            let instance_range = first_instance..instance_table.transform_indexes.len() as u32;
+            if !instance_range.is_empty() {
+                instance_table.mesh_instance_ranges.push((mesh.get_name().clone(), instance_range));
+            }
+        }
+        instance_table
     }
 
-    pub fn get_last_vertex_index(&self) -> usize {
-        self.meshes.iter().fold(0, |_, mesh| {
-            mesh.data.iter().count()
+    pub fn mesh_location(&self, mesh_name: &str) -> Option<MeshLocation> {
+        let mesh = self.meshes.iter().find(|mesh| mesh.get_name() == mesh_name)?;
+        Some(MeshLocation {
+            first_vertex_index: self.mesh_name_first_vertex_index_map[mesh_name],
+            vertex_count: mesh.data.len(),
+            first_index: self.mesh_name_first_index_map[mesh_name],
+            index_count: mesh.indices.len()
         })
     }
-}
\ No newline at end of file
+
+    // used when the mesh's data was moved, e.g. by compacting the vertex buffer
+    pub fn set_mesh_location(&mut self, mesh_name: &str, first_vertex_index: usize, first_index: usize) {
+        self.mesh_name_first_vertex_index_map.insert(mesh_name.to_owned(), first_vertex_index);
+        self.mesh_name_first_index_map.insert(mesh_name.to_owned(), first_index);
+    }
+
+    // meshes whose entities were all removed
+    pub fn unused_mesh_names(&self) -> Vec<String> {
+        self.meshes.iter()
+            .filter(|mesh| self.mesh_name_instance_count_map[mesh.get_name()] == 0)
+            .map(|mesh| mesh.get_name().clone())
+            .collect()
+    }
+
+    // only meshes without instances can be removed, returns where the removed mesh's data was
+    pub fn remove_mesh(&mut self, mesh_name: &str) -> Option<MeshLocation> {
+        if *self.mesh_name_instance_count_map.get(mesh_name)? != 0 {
+            return None;
+        }
+        let mesh_location = self.mesh_location(mesh_name)?;
+        self.mesh_name_instance_count_map.remove(mesh_name);
+        self.mesh_name_first_vertex_index_map.remove(mesh_name);
+        self.mesh_name_first_index_map.remove(mesh_name);
+        self.mesh_name_visible_entities_map.remove(mesh_name);
+        self.meshes.retain(|mesh| mesh.get_name() != mesh_name);
+        Some(mesh_location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{engine::entity_id::EntityIdAllocator, rendering::primitives::Vertex};
+
+    use super::*;
+
+    fn mesh(name: &str, vertex_count: usize, index_count: usize) -> Mesh {
+        Mesh::new(vec![Vertex::default(); vertex_count], vec![0; index_count], name.to_owned())
+    }
+
+    fn mesh_accessor_with_mesh(name: &str) -> MeshAccessor {
+        let mut mesh_accessor = MeshAccessor::new();
+        mesh_accessor.add_mesh(mesh(name, 8, 36), 0, 0);
+        mesh_accessor
+    }
+
+    #[test]
+    fn meshes_are_found_at_the_location_they_were_added_at() {
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        mesh_accessor.add_mesh(mesh("b", 4, 6), 8, 36);
+
+        assert_eq!(mesh_accessor.mesh_location("b"), Some(MeshLocation { first_vertex_index: 8, vertex_count: 4, first_index: 36, index_count: 6 }));
+        mesh_accessor.set_mesh_location("b", 100, 200);
+        assert_eq!(mesh_accessor.mesh_name_first_vertex_index_map["b"], 100);
+        assert_eq!(mesh_accessor.mesh_name_first_index_map["b"], 200);
+        assert_eq!(mesh_accessor.mesh_location("c"), None);
+    }
+
+    #[test]
+    fn adding_entities_counts_instances() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        assert_eq!(mesh_accessor.mesh_name_instance_count_map["a"], 0);
+        assert!(mesh_accessor.add_entity("a", entity_ids.allocate()));
+        assert!(mesh_accessor.add_entity("a", entity_ids.allocate()));
+        assert!(!mesh_accessor.add_entity("unknown", entity_ids.allocate()));
+
+        assert_eq!(mesh_accessor.meshes.len(), 1);
+        assert_eq!(mesh_accessor.mesh_name_instance_count_map["a"], 2);
+    }
+
+    #[test]
+    fn removing_an_entity_decrements_its_mesh_instance_count() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        let removed_entity_id = entity_ids.allocate();
+        mesh_accessor.add_entity("a", removed_entity_id);
+        mesh_accessor.add_entity("a", entity_ids.allocate());
+
+        assert_eq!(mesh_accessor.remove_entity(removed_entity_id), Some("a".to_owned()));
+        assert_eq!(mesh_accessor.mesh_name_instance_count_map["a"], 1);
+        assert_eq!(mesh_accessor.remove_entity(removed_entity_id), None);
+        assert_eq!(mesh_accessor.meshes.len(), 1);
+    }
+
+    #[test]
+    fn only_unused_meshes_can_be_removed() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        mesh_accessor.add_mesh(mesh("b", 4, 6), 8, 36);
+        let entity_id = entity_ids.allocate();
+        mesh_accessor.add_entity("a", entity_id);
+
+        assert_eq!(mesh_accessor.unused_mesh_names(), vec!["b".to_owned()]);
+        assert_eq!(mesh_accessor.remove_mesh("a"), None);
+        mesh_accessor.remove_entity(entity_id);
+        assert_eq!(mesh_accessor.remove_mesh("a"), Some(MeshLocation { first_vertex_index: 0, vertex_count: 8, first_index: 0, index_count: 36 }));
+        assert!(!mesh_accessor.contains_mesh("a"));
+        assert_eq!(mesh_accessor.meshes.len(), 1);
+        assert_eq!(mesh_accessor.remove_mesh("a"), None);
+    }
+
+    #[test]
+    fn hidden_entities_are_removed_from_the_visible_list() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        let ids: Vec<EntityId> = (0..3).map(|_| entity_ids.allocate()).collect();
+        for id in ids.iter() {
+            mesh_accessor.add_entity("a", *id);
+        }
+
+        assert!(mesh_accessor.set_entity_visibility(ids[0], Visibility::Invisible));
+        assert!(!mesh_accessor.is_entity_visible(ids[0]));
+        assert_eq!(mesh_accessor.visible_entities("a").len(), 2);
+        assert!(!mesh_accessor.visible_entities("a").contains(&ids[0]));
+        // hiding twice is a no op
+        mesh_accessor.set_entity_visibility(ids[0], Visibility::Invisible);
+        assert_eq!(mesh_accessor.visible_entities("a").len(), 2);
+        // the instance count still includes hidden entities
+        assert_eq!(mesh_accessor.mesh_name_instance_count_map["a"], 3);
+    }
+
+    #[test]
+    fn shown_entities_are_drawn_again() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        let ids: Vec<EntityId> = (0..3).map(|_| entity_ids.allocate()).collect();
+        for id in ids.iter() {
+            mesh_accessor.add_entity("a", *id);
+        }
+        mesh_accessor.set_entity_visibility(ids[0], Visibility::Invisible);
+        mesh_accessor.set_entity_visibility(ids[1], Visibility::Invisible);
+        mesh_accessor.set_entity_visibility(ids[0], Visibility::Visible);
+
+        let mut visible_entities = mesh_accessor.visible_entities("a").to_vec();
+        visible_entities.sort();
+        assert_eq!(visible_entities, vec![ids[0], ids[2]]);
+        // removing a hidden entity must not touch the visible list
+        mesh_accessor.remove_entity(ids[1]);
+        assert_eq!(mesh_accessor.visible_entities("a").len(), 2);
+        mesh_accessor.remove_entity(ids[2]);
+        assert_eq!(mesh_accessor.visible_entities("a"), &[ids[0]]);
+        assert!(!mesh_accessor.set_entity_visibility(ids[2], Visibility::Visible));
+    }
+
+    #[test]
+    fn every_mesh_draws_the_transforms_of_its_own_entities() {
+        let mut entity_ids = EntityIdAllocator::new();
+        let mut mesh_accessor = mesh_accessor_with_mesh("a");
+        mesh_accessor.add_mesh(mesh("b", 4, 6), 8, 36);
+        mesh_accessor.add_mesh(mesh("c", 4, 6), 12, 42);
+        // spawned interleaved, so neither mesh's transform slots are contiguous
+        let ids: Vec<EntityId> = (0..5).map(|_| entity_ids.allocate()).collect();
+        for (id, mesh_name) in ids.iter().zip(["b", "a", "b", "a", "b"]) {
+            mesh_accessor.add_entity(mesh_name, *id);
+        }
+        mesh_accessor.set_entity_visibility(ids[2], Visibility::Invisible);
+        let transform_index = |entity_id: EntityId| ids.iter().position(|id| *id == entity_id);
+
+        let instance_table = mesh_accessor.build_instance_table(transform_index);
+        assert_eq!(instance_table.transform_indexes, vec![1, 3, 0, 4]);
+        assert_eq!(instance_table.mesh_instance_ranges, vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..4)]);
+    }
+}
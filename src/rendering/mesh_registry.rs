@@ -0,0 +1,129 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::primitives::Mesh;
+
+// refers to a mesh registered in a MeshRegistry, cheap to copy into components and events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshHandle(u32);
+
+impl MeshHandle {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+// two meshes with different content ended up with the same content hash, both are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshHashCollision {
+    pub content_hash: u64,
+    pub existing: MeshHandle,
+    pub colliding: MeshHandle,
+}
+
+// owns every mesh the engine knows about, registering a mesh whose content is already known returns the existing handle.
+// names are unique, a different mesh registered under a taken name gets the handle appended to its name
+#[derive(Debug, Default)]
+pub struct MeshRegistry {
+    meshes: Vec<Arc<Mesh>>,
+    content_hash_handles: HashMap<u64, Vec<MeshHandle>>,
+    mesh_name_handles: HashMap<String, MeshHandle>,
+    collisions: Vec<MeshHashCollision>,
+}
+
+impl MeshRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, mesh: Mesh) -> MeshHandle {
+        let content_hash = mesh.content_hash();
+        self.register_with_content_hash(mesh, content_hash)
+    }
+
+    fn register_with_content_hash(&mut self, mut mesh: Mesh, content_hash: u64) -> MeshHandle {
+        let candidates = self.content_hash_handles.get(&content_hash).cloned().unwrap_or_default();
+        // the hash only narrows the search, equal content is what makes two meshes the same
+        if let Some(existing) = candidates.iter().find(|handle| *self.meshes[handle.index()] == mesh) {
+            return *existing;
+        }
+
+        let handle = MeshHandle(self.meshes.len() as u32);
+        if let Some(existing) = candidates.first() {
+            self.collisions.push(MeshHashCollision { content_hash, existing: *existing, colliding: handle });
+        }
+        if self.mesh_name_handles.contains_key(mesh.get_name()) {
+            mesh.name = format!("{}#{}", mesh.get_name(), handle.0);
+        }
+        self.mesh_name_handles.insert(mesh.get_name().clone(), handle);
+        self.content_hash_handles.entry(content_hash).or_default().push(handle);
+        self.meshes.push(Arc::new(mesh));
+        handle
+    }
+
+    pub fn get(&self, handle: MeshHandle) -> Option<&Arc<Mesh>> {
+        self.meshes.get(handle.index())
+    }
+
+    pub fn handle_by_name(&self, mesh_name: &str) -> Option<MeshHandle> {
+        self.mesh_name_handles.get(mesh_name).copied()
+    }
+
+    pub fn collisions(&self) -> &[MeshHashCollision] {
+        &self.collisions
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rendering::{primitives::Cube, rendering_traits::HasMesh};
+
+    use super::*;
+
+    fn cube_mesh(name: &str, size: f32) -> Mesh {
+        Cube::new(glam::Vec3::splat(size)).get_mesh(name.to_owned())
+    }
+
+    #[test]
+    fn meshes_with_the_same_content_share_a_handle() {
+        let mut mesh_registry = MeshRegistry::new();
+        let handle = mesh_registry.register(cube_mesh("Cube", 0.25));
+        assert_eq!(mesh_registry.register(cube_mesh("Other cube", 0.25)), handle);
+        assert_ne!(mesh_registry.register(cube_mesh("Big cube", 1.)), handle);
+
+        assert_eq!(mesh_registry.len(), 2);
+        assert_eq!(mesh_registry.get(handle).unwrap().get_name(), "Cube");
+        assert_eq!(mesh_registry.handle_by_name("Other cube"), None);
+        assert!(mesh_registry.collisions().is_empty());
+    }
+
+    #[test]
+    fn different_meshes_with_a_taken_name_are_renamed() {
+        let mut mesh_registry = MeshRegistry::new();
+        let small_cube = mesh_registry.register(cube_mesh("Cube", 0.25));
+        let big_cube = mesh_registry.register(cube_mesh("Cube", 1.));
+
+        assert_eq!(mesh_registry.handle_by_name("Cube"), Some(small_cube));
+        assert_eq!(mesh_registry.get(big_cube).unwrap().get_name(), "Cube#1");
+        assert_eq!(mesh_registry.handle_by_name("Cube#1"), Some(big_cube));
+    }
+
+    #[test]
+    fn hash_collisions_are_reported_and_keep_both_meshes() {
+        let mut mesh_registry = MeshRegistry::new();
+        let existing = mesh_registry.register_with_content_hash(cube_mesh("Small", 0.25), 42);
+        let colliding = mesh_registry.register_with_content_hash(cube_mesh("Big", 1.), 42);
+        assert_ne!(existing, colliding);
+        assert_eq!(mesh_registry.collisions(), &[MeshHashCollision { content_hash: 42, existing, colliding }]);
+        // both are still found by content
+        assert_eq!(mesh_registry.register_with_content_hash(cube_mesh("Big again", 1.), 42), colliding);
+        assert_eq!(mesh_registry.collisions().len(), 1);
+    }
+}
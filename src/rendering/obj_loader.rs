@@ -0,0 +1,143 @@
+use std::{error::Error, io::BufRead, path::Path};
+
+use super::primitives::{Mesh, Vertex};
+
+fn load_options() -> tobj::LoadOptions {
+    tobj::LoadOptions {
+        // polygons get fan triangulated, and positions, normals and uvs share one index so they map onto our vertices
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+    }
+}
+
+// every object/group of the file becomes its own mesh, named "<file stem>/<object name>"
+pub fn load_obj(path: &Path) -> Result<Vec<Mesh>, Box<dyn Error>> {
+    let file_name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("obj");
+    let (models, _materials) = tobj::load_obj(path, &load_options())?;
+    Ok(models_to_meshes(models, file_name))
+}
+
+// same as load_obj, but from any reader (material libraries are ignored)
+pub fn parse_obj(reader: &mut impl BufRead, name: &str) -> Result<Vec<Mesh>, Box<dyn Error>> {
+    let (models, _materials) = tobj::load_obj_buf(reader, &load_options(), |_| Err(tobj::LoadError::OpenFileFailed))?;
+    Ok(models_to_meshes(models, name))
+}
+
+fn models_to_meshes(models: Vec<tobj::Model>, name: &str) -> Vec<Mesh> {
+    models.into_iter()
+        .filter(|model| !model.mesh.indices.is_empty())
+        .map(|model| model_to_mesh(model, name))
+        .collect()
+}
+
+fn model_to_mesh(model: tobj::Model, name: &str) -> Mesh {
+    let obj_mesh = model.mesh;
+    let has_normals = !obj_mesh.normals.is_empty();
+    let has_uvs = !obj_mesh.texcoords.is_empty();
+    let has_colors = !obj_mesh.vertex_color.is_empty();
+
+    let data = (0..obj_mesh.positions.len() / 3).map(|i| {
+        let normal = match has_normals {
+            true => [obj_mesh.normals[3 * i], obj_mesh.normals[3 * i + 1], obj_mesh.normals[3 * i + 2]],
+            false => [0., 0., 0.],
+        };
+        // obj has its uv origin at the bottom left, vulkan at the top left
+        let uv = match has_uvs {
+            true => [obj_mesh.texcoords[2 * i], 1. - obj_mesh.texcoords[2 * i + 1]],
+            false => [0., 0.],
+        };
+        let color = match has_colors {
+            true => [obj_mesh.vertex_color[3 * i], obj_mesh.vertex_color[3 * i + 1], obj_mesh.vertex_color[3 * i + 2], 1.],
+            false => [1., 1., 1., 1.],
+        };
+        Vertex {
+            position: [obj_mesh.positions[3 * i], obj_mesh.positions[3 * i + 1], obj_mesh.positions[3 * i + 2]],
+            normal,
+            uv,
+            color,
+            ..Default::default()
+        }
+    })
+    .collect();
+
+    let mut mesh = Mesh::new(data, obj_mesh.indices, format!("{}/{}", name, model.name));
+    mesh.mirror_z();
+    if !has_normals {
+        mesh.recalculate_normals();
+    }
+    mesh.recalculate_tangents();
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    const TWO_OBJECTS: &str = "
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g triangle
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f 5 6 7
+";
+
+    #[test]
+    fn objects_and_groups_become_separate_meshes() {
+        let meshes = parse_obj(&mut TWO_OBJECTS.as_bytes(), "test").unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].name, "test/quad");
+        assert_eq!(meshes[1].name, "test/triangle");
+    }
+
+    #[test]
+    fn polygons_are_triangulated() {
+        let meshes = parse_obj(&mut TWO_OBJECTS.as_bytes(), "test").unwrap();
+        assert_eq!(meshes[0].data.len(), 4);
+        assert_eq!(meshes[0].indices.len(), 6);
+        assert_eq!(meshes[1].indices.len(), 3);
+    }
+
+    #[test]
+    fn normals_and_flipped_uvs_are_read() {
+        let meshes = parse_obj(&mut TWO_OBJECTS.as_bytes(), "test").unwrap();
+        let first_vertex = meshes[0].data[0];
+        assert_eq!(first_vertex.normal, [0., 0., -1.]);
+        assert_eq!(first_vertex.uv, [0., 1.]);
+        assert_eq!(&first_vertex.tangent[0..3], &[1., 0., 0.]);
+    }
+
+    #[test]
+    fn missing_normals_are_calculated() {
+        let meshes = parse_obj(&mut TWO_OBJECTS.as_bytes(), "test").unwrap();
+        for vertex in meshes[1].data.iter() {
+            assert!(Vec3::from(vertex.normal).abs_diff_eq(-Vec3::Z, 1e-6));
+        }
+    }
+
+    #[test]
+    fn right_handed_meshes_are_mirrored_to_left_handed() {
+        let meshes = parse_obj(&mut TWO_OBJECTS.as_bytes(), "test").unwrap();
+        let triangle = &meshes[1];
+        assert_eq!(triangle.indices, vec![0, 2, 1]);
+        let positions: Vec<[f32; 3]> = triangle.data.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![[0., 0., -1.], [1., 0., -1.], [0., 1., -1.]]);
+        // the quad faces +z in the file and the mirrored quad -z, so it still faces a camera looking at it from the same side
+        let quad = &meshes[0];
+        assert!(quad.data.iter().all(|vertex| vertex.normal == [0., 0., -1.]));
+        assert_eq!(&quad.indices[0..3], &[0, 2, 1]);
+    }
+}
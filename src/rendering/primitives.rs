@@ -1,24 +1,56 @@
-use std::{collections::hash_map::DefaultHasher, hash::Hasher, ops::{Deref, DerefMut}};
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::Hasher, ops::{Deref, DerefMut}};
 
 use bytemuck::{Zeroable, Pod};
 use glam::Vec3;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex as VertexMacro};
 
-use crate::{engine::general_traits::{Entity, TickAction}, physics::physics_traits::{HasTransform, Movable, Transform}};
 use core::hash::Hash;
-use super::rendering_traits::{HasMesh, RenderableEntity};
+use super::rendering_traits::HasMesh;
 
-use nanoid::nanoid;
 
 
 
 #[repr(C)]
-#[derive(Default, Copy, Clone, Debug, BufferContents, VertexMacro)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, BufferContents, VertexMacro, Serialize, Deserialize)]
 pub struct Vertex {
     #[format(R32G32B32_SFLOAT)]
     pub position: [f32; 3],
-    //pub color: [f32; 4]
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    // xyz is the tangent, w the handedness of the bitangent (+1 or -1)
+    #[format(R32G32B32A32_SFLOAT)]
+    pub tangent: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    pub fn new(position: [f32; 3], normal: [f32; 3], uv: [f32; 2], tangent: [f32; 4]) -> Self {
+        Self {
+            position,
+            normal,
+            uv,
+            tangent,
+            color: [1., 1., 1., 1.],
+        }
+    }
+
+    // bitwise representation of all attributes, used to merge identical vertices
+    pub fn bit_key(&self) -> [u32; 16] {
+        let mut key = [0_u32; 16];
+        let attributes = self.position.iter()
+            .chain(self.normal.iter())
+            .chain(self.uv.iter())
+            .chain(self.tangent.iter())
+            .chain(self.color.iter());
+        for (bits, attribute) in key.iter_mut().zip(attributes) {
+            *bits = attribute.to_bits();
+        }
+        key
+    }
 }
 
 impl Deref for Vertex {
@@ -41,43 +73,145 @@ impl DerefMut for Vertex {
 pub struct Mesh {
     pub name: String,
     pub data: Vec<Vertex>,
+    // indices into data (so starting at 0 for every mesh), 3 per triangle
+    pub indices: Vec<u32>,
 }
 
 impl Mesh {
-    pub fn new(data: Vec<Vertex>, name: String) -> Self {
+    pub fn new(data: Vec<Vertex>, indices: Vec<u32>, name: String) -> Self {
         Self {
             name,
             data,
+            indices,
         }
     }
 
+    // builds an indexed mesh out of triangle soup, bitwise identical vertices get merged into one
+    pub fn from_triangles(triangles: &[Triangle], name: String) -> Self {
+        let mut data = Vec::new();
+        let mut indices = Vec::new();
+        let mut vertex_to_index: HashMap<[u32; 16], u32> = HashMap::new();
+        for triangle in triangles {
+            for vertex in triangle.vertices {
+                let index = *vertex_to_index.entry(vertex.bit_key()).or_insert_with(|| {
+                    data.push(vertex);
+                    (data.len() - 1) as u32
+                });
+                indices.push(index);
+            }
+        }
+        Self::new(data, indices, name)
+    }
+
     pub fn get_name(&self) -> &String {
         &self.name
     }
+
+    // gltf and obj are right handed, the engine left handed with +z forward. mirroring z converts between them,
+    // swapping two corners of every triangle keeps the mirrored triangles facing the same side
+    pub fn mirror_z(&mut self) {
+        for vertex in self.data.iter_mut() {
+            vertex.position[2] = -vertex.position[2];
+            vertex.normal[2] = -vertex.normal[2];
+            // the mirrored bitangent needs the opposite handedness
+            vertex.tangent[2] = -vertex.tangent[2];
+            vertex.tangent[3] = -vertex.tangent[3];
+        }
+        for triangle in self.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // smooth normals, every vertex gets the area weighted average of the normals of the triangles it is part of
+    pub fn recalculate_normals(&mut self) -> () {
+        let mut normals = vec![Vec3::ZERO; self.data.len()];
+        for triangle_indices in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(self.data[triangle_indices[i] as usize].position));
+            let face_normal = (b - a).cross(c - a);
+            for index in triangle_indices {
+                normals[*index as usize] += face_normal;
+            }
+        }
+        for (vertex, normal) in self.data.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or_zero().to_array();
+        }
+    }
+
+    // tangents along the u direction of the uvs, expects normals and uvs to be set already
+    pub fn recalculate_tangents(&mut self) -> () {
+        let mut tangents = vec![Vec3::ZERO; self.data.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.data.len()];
+        for triangle_indices in self.indices.chunks_exact(3) {
+            let vertices = [0, 1, 2].map(|i| self.data[triangle_indices[i] as usize]);
+            let edge_1 = Vec3::from(vertices[1].position) - Vec3::from(vertices[0].position);
+            let edge_2 = Vec3::from(vertices[2].position) - Vec3::from(vertices[0].position);
+            let (du_1, dv_1) = (vertices[1].uv[0] - vertices[0].uv[0], vertices[1].uv[1] - vertices[0].uv[1]);
+            let (du_2, dv_2) = (vertices[2].uv[0] - vertices[0].uv[0], vertices[2].uv[1] - vertices[0].uv[1]);
+            let determinant = du_1 * dv_2 - du_2 * dv_1;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge_1 * dv_2 - edge_2 * dv_1) / determinant;
+            let bitangent = (edge_2 * du_1 - edge_1 * du_2) / determinant;
+            for index in triangle_indices {
+                tangents[*index as usize] += tangent;
+                bitangents[*index as usize] += bitangent;
+            }
+        }
+        for ((vertex, tangent), bitangent) in self.data.iter_mut().zip(tangents).zip(bitangents) {
+            let normal = Vec3::from(vertex.normal);
+            // gram-schmidt, falls back to any vector perpendicular to the normal if there are no usable uvs
+            let mut orthogonal_tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+            if orthogonal_tangent == Vec3::ZERO {
+                orthogonal_tangent = normal.any_orthonormal_vector();
+            }
+            let handedness = if normal.cross(orthogonal_tangent).dot(bitangent) < 0. { -1. } else { 1. };
+            vertex.tangent = orthogonal_tangent.extend(handedness).to_array();
+        }
+    }
 }
 
-impl PartialEq for Mesh {
-    fn eq(&self, other: &Self) -> bool {
+// attributes are compared on a grid of 1/65536, so float noise from e.g. different import paths doesn't keep identical meshes apart
+const MESH_CONTENT_QUANTIZATION: f32 = 65536.;
+
+impl Mesh {
+    // every vertex attribute quantized, in vertex order
+    fn quantized_attributes(&self) -> impl Iterator<Item = i64> + '_ {
+        self.data.iter()
+            .flat_map(|vertex| vertex.position.iter()
+                .chain(vertex.normal.iter())
+                .chain(vertex.uv.iter())
+                .chain(vertex.tangent.iter())
+                .chain(vertex.color.iter()))
+            .map(|attribute| (attribute * MESH_CONTENT_QUANTIZATION).round() as i64)
+    }
+
+    // stable for equal content, the name is not part of it
+    pub fn content_hash(&self) -> u64 {
         let mut hasher = DefaultHasher::new();
-        self.hash(& mut hasher) == other.hash(& mut hasher)
+        self.hash(&mut hasher);
+        hasher.finish()
     }
 }
 
-impl Eq for Mesh {
-
+// meshes are equal if their quantized vertices and their indices are, no matter how they are named
+impl PartialEq for Mesh {
+    fn eq(&self, other: &Self) -> bool {
+        self.data.len() == other.data.len()
+            && self.indices == other.indices
+            && self.quantized_attributes().eq(other.quantized_attributes())
+    }
 }
 
+impl Eq for Mesh {}
+
 impl Hash for Mesh {
-    fn hash<H>(&self, state: &mut H) where H: Hasher { 
-        let mut result = Vec::new();
-        for triangle in &self.data {
-            for j in triangle.position {
-                let rounded_coord =  (j * 100_f32) as u8;
-                result.push(rounded_coord);
-            }
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        self.data.len().hash(state);
+        for attribute in self.quantized_attributes() {
+            attribute.hash(state);
         }
-        state.write(&result);
-        state.finish();
+        self.indices.hash(state);
     }
 }
 
@@ -104,146 +238,98 @@ impl Triangle {
 #[derive(Debug, Clone)]
 pub struct Cube {
     pub bounds: Vec3,
-    transform: Transform,
-    mesh: Option<Mesh>,
-    id: String,
 }
 
 impl Cube {
-    pub fn new(bounds: Vec3, transform: Transform) -> Self {
+    pub fn new(bounds: Vec3) -> Self {
         Self {
             bounds,
-            transform,
-            mesh: None,
-            id: nanoid!()
         }
     }
 }
 
-impl RenderableEntity for Cube {}
-
-impl HasTransform for Cube {
-    fn get_transform(&self) -> Transform {
-        self.transform
-    }
-}
-
-impl Entity for Cube {
-    fn tick(&mut self) -> Option<TickAction> {
-        let amount: f32 = rand::thread_rng().gen_range(-0.02_f32..0.02_f32);
-        self.move_x(amount);
-        Some(TickAction::HasMoved(self.transform))
-    }
-}
-
 impl Default for Cube {
     fn default() -> Self {
-        let bounds = Vec3 { x: 0.25, y: 0.125, z: 0.25 };
-        
-        Self {
-            bounds : bounds,
-            transform: Transform::default(),
-            mesh: None,
-            id: nanoid!()
-        }
-    }
-}
-
-impl Movable for Cube {
-    fn update_position(&mut self) -> () {
-        let amount: f32 = rand::thread_rng().gen_range(-2_f32..2_f32);
-        self.move_x(amount);
-    }
-
-    fn move_xyz(&mut self, amount: Vec3) -> () {
-        self.move_x(amount.x);
-        self.move_y(amount.y);
-        self.move_z(amount.z);
-        self.on_move();
-    }
-    fn move_x(&mut self, amount: f32) -> () {
-        self.transform.translation.x += amount;
-        self.on_move();
-    }
-    fn move_y(&mut self, amount: f32) -> () {
-        self.transform.translation.y += amount;
-        self.on_move();
+        Self::new(Vec3 { x: 0.25, y: 0.125, z: 0.25 })
     }
-    fn move_z(&mut self, amount: f32) -> () {
-        self.transform.translation.z += amount;
-        self.on_move();
-    }
-
-    fn on_move(&mut self) -> () {
-        return
-    }
-
 }
 
 impl HasMesh for Cube {
     fn get_mesh(&mut self, name: String) -> Mesh {
-        let mut result = Vec::new();
-        for triangle in self.get_data() {
-            for i in 0..triangle.vertices.len() {
-                result.push(triangle.vertices[i])
-            }
-        }
-        Mesh::new(result, name)
+        Mesh::from_triangles(&self.get_data(), name)
     }
 
+    // 4 vertices per face so every face gets its own normal and uvs, triangles are wound counter clockwise seen from outside
     fn get_data(& self) -> Vec<Triangle> {
+        let half_bounds = self.bounds / 2.;
+        // (normal, tangent, bitangent) per face, tangent x bitangent = normal
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ];
+        let corners = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)];
+
         let mut resulting_mesh: Vec<Triangle> = Vec::new();
-        let (x_bounds, y_bounds, z_bounds) = (self.bounds[0], self.bounds[1], self.bounds[2]);
+        for (normal, tangent, bitangent) in faces {
+            let face_vertices = corners.map(|(u, v): (f32, f32)| {
+                let position = (normal + tangent * u + bitangent * v) * half_bounds;
+                let uv = [(u + 1.) / 2., (1. - v) / 2.];
+                Vertex::new(position.to_array(), normal.to_array(), uv, tangent.extend(1.).to_array())
+            });
+            resulting_mesh.push(Triangle::new(face_vertices[0], face_vertices[1], face_vertices[2]));
+            resulting_mesh.push(Triangle::new(face_vertices[0], face_vertices[2], face_vertices[3]));
+        }
 
-        let temp = [[x_bounds/2., y_bounds/2., z_bounds/2.]; 8];
-        let mut temp_vertices: Vec<Vertex> = Vec::new();
+        resulting_mesh
+    }
+}
 
-        //terrible, creates x,y,z arrays for the points in this order, right_top_front, left_top_front, right_bottom_front, 
-        //right_top_back, right_bottom_back, left_top_back, left_bottom_front, left_bottom_back
-        for i in 0..temp.len() {
-            let mut temp_sub = temp[i];
-            if i > 0 && i < 4 {
-                temp_sub[i-1] = temp_sub[i-1] * -1.
-            }
-            else if i >= 4 && i <= 7 {
-                for j in 0..temp_sub.len() {
-                    temp_sub[j] = temp_sub[j] * -1.;
-                }
-                if i == 7 {
-                    temp_vertices.push(Vertex{position: temp_sub});
-                    break;
-                }
-                temp_sub[i - temp_sub.len()-1] = temp_sub[i - temp_sub.len()-1] * -1.
-            }
-            temp_vertices.push(Vertex{position: temp_sub});
-        }
-
-        let triangle_1 = Triangle{vertices: [temp_vertices[0], temp_vertices[3], temp_vertices[5]]};
-        let triangle_2 = Triangle{vertices: [temp_vertices[5], temp_vertices[1], temp_vertices[0]]};
-        let triangle_3 = Triangle{vertices: [temp_vertices[3], temp_vertices[4], temp_vertices[7]]};
-        let triangle_4 = Triangle{vertices: [temp_vertices[7], temp_vertices[3], temp_vertices[5]]};
-        let triangle_5 = Triangle{vertices: [temp_vertices[1], temp_vertices[5], temp_vertices[7]]};
-        let triangle_6 = Triangle{vertices: [temp_vertices[6], temp_vertices[1], temp_vertices[7]]};
-        let triangle_7 = Triangle{vertices: [temp_vertices[6], temp_vertices[0], temp_vertices[1]]};
-        let triangle_8 = Triangle{vertices: [temp_vertices[2], temp_vertices[0], temp_vertices[6]]};
-        let triangle_9 = Triangle{vertices: [temp_vertices[2], temp_vertices[3], temp_vertices[0]]};
-        let triangle_10 = Triangle{vertices: [temp_vertices[4], temp_vertices[3], temp_vertices[2]]};
-        let triangle_11 = Triangle{vertices: [temp_vertices[6], temp_vertices[2], temp_vertices[7]]};
-        let triangle_12 = Triangle{vertices: [temp_vertices[2], temp_vertices[4], temp_vertices[7]]};
-        
-        resulting_mesh.push(triangle_1);
-        resulting_mesh.push(triangle_2);
-        resulting_mesh.push(triangle_3);
-        resulting_mesh.push(triangle_4);
-        resulting_mesh.push(triangle_5);
-        resulting_mesh.push(triangle_6);
-        resulting_mesh.push(triangle_7);
-        resulting_mesh.push(triangle_8);
-        resulting_mesh.push(triangle_9);
-        resulting_mesh.push(triangle_10);
-        resulting_mesh.push(triangle_11);
-        resulting_mesh.push(triangle_12);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_mesh_is_indexed_with_shared_face_corners() {
+        let mut cube = Cube::default();
+        let mesh = cube.get_mesh("Cube".to_owned());
+        assert_eq!(mesh.data.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert!(mesh.indices.iter().all(|index| (*index as usize) < mesh.data.len()));
+    }
 
-        resulting_mesh
+    #[test]
+    fn cube_faces_have_outward_normals_and_winding() {
+        let cube = Cube::default();
+        for triangle in cube.get_data() {
+            let [a, b, c] = triangle.vertices.map(|vertex| Vec3::from(vertex.position));
+            let normal = Vec3::from(triangle.vertices[0].normal);
+            assert!((b - a).cross(c - a).dot(normal) > 0.);
+            assert!(a.dot(normal) > 0.);
+            assert!(triangle.vertices.iter().all(|vertex| vertex.uv.iter().all(|coordinate| (0.0..=1.0).contains(coordinate))));
+        }
+    }
+
+    #[test]
+    fn meshes_with_the_same_content_are_equal_regardless_of_name_and_float_noise() {
+        let mut cube = Cube::default();
+        let mesh = cube.get_mesh("a".to_owned());
+        let mut noisy_mesh = cube.get_mesh("b".to_owned());
+        noisy_mesh.data[0].position[0] += 1e-7;
+        assert_eq!(mesh, noisy_mesh);
+        assert_eq!(mesh.content_hash(), noisy_mesh.content_hash());
+
+        // the old hash truncated to u8 and couldn't tell these apart
+        let mut moved_mesh = cube.get_mesh("a".to_owned());
+        moved_mesh.data[0].position[0] += 0.001;
+        assert_ne!(mesh, moved_mesh);
+        assert_ne!(mesh.content_hash(), moved_mesh.content_hash());
+
+        let mut reindexed_mesh = cube.get_mesh("a".to_owned());
+        reindexed_mesh.indices.swap(0, 1);
+        assert_ne!(mesh, reindexed_mesh);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,331 @@
+use std::{collections::HashMap, f32::consts::{FRAC_PI_2, PI, TAU}};
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::primitives::{Mesh, Vertex};
+
+// parameterized meshes generated on the cpu, all centered at the origin with y up, see Engine::add_primitive_to_scene
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Primitive {
+    UvSphere { radius: f32, segments: u32, rings: u32 },
+    // every subdivision splits each triangle of the icosahedron into 4
+    Icosphere { radius: f32, subdivisions: u32 },
+    // in the xz plane, facing up
+    Plane { width: f32, depth: f32, segments_x: u32, segments_z: u32 },
+    Cylinder { radius: f32, height: f32, segments: u32 },
+    Cone { radius: f32, height: f32, segments: u32 },
+    // major radius from the center to the middle of the tube, minor radius of the tube
+    Torus { major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32 },
+    // height of the cylindric part between the two hemispheres, rings per hemisphere
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 },
+}
+
+impl Primitive {
+    // unique per kind and parameters, so the same primitive is only generated once per engine
+    pub fn mesh_name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        let mut mesh_builder = MeshBuilder::default();
+        match *self {
+            Primitive::UvSphere { radius, segments, rings } => {
+                let profile = (0..=rings.max(2))
+                    .map(|ring| ProfilePoint::on_sphere(radius, PI * ring as f32 / rings.max(2) as f32, 0.))
+                    .collect::<Vec<_>>();
+                mesh_builder.push_lathe(&profile, segments);
+            }
+            Primitive::Icosphere { radius, subdivisions } => mesh_builder.push_icosphere(radius, subdivisions),
+            Primitive::Plane { width, depth, segments_x, segments_z } => mesh_builder.push_plane(width, depth, segments_x, segments_z),
+            Primitive::Cylinder { radius, height, segments } => {
+                let (top, bottom) = (height / 2., -height / 2.);
+                mesh_builder.push_lathe(&[ProfilePoint::new(0., top, Vec2::Y), ProfilePoint::new(radius, top, Vec2::Y)], segments);
+                mesh_builder.push_lathe(&[ProfilePoint::new(radius, top, Vec2::X), ProfilePoint::new(radius, bottom, Vec2::X)], segments);
+                mesh_builder.push_lathe(&[ProfilePoint::new(radius, bottom, Vec2::NEG_Y), ProfilePoint::new(0., bottom, Vec2::NEG_Y)], segments);
+            }
+            Primitive::Cone { radius, height, segments } => {
+                let (top, bottom) = (height / 2., -height / 2.);
+                let side_normal = Vec2::new(height, radius).normalize();
+                mesh_builder.push_lathe(&[ProfilePoint::new(0., top, side_normal), ProfilePoint::new(radius, bottom, side_normal)], segments);
+                mesh_builder.push_lathe(&[ProfilePoint::new(radius, bottom, Vec2::NEG_Y), ProfilePoint::new(0., bottom, Vec2::NEG_Y)], segments);
+            }
+            Primitive::Torus { major_radius, minor_radius, major_segments, minor_segments } => {
+                // starts at the outer equator and goes down first, like every other profile
+                let profile = (0..=minor_segments.max(3))
+                    .map(|minor_segment| {
+                        let angle = TAU * minor_segment as f32 / minor_segments.max(3) as f32;
+                        let normal = Vec2::new(angle.cos(), -angle.sin());
+                        ProfilePoint::new(major_radius + minor_radius * normal.x, minor_radius * normal.y, normal)
+                    })
+                    .collect::<Vec<_>>();
+                mesh_builder.push_lathe(&profile, major_segments);
+            }
+            Primitive::Capsule { radius, height, segments, rings } => {
+                let rings = rings.max(1);
+                let top_hemisphere = (0..=rings).map(|ring| ProfilePoint::on_sphere(radius, FRAC_PI_2 * ring as f32 / rings as f32, height / 2.));
+                let bottom_hemisphere = (0..=rings).map(|ring| ProfilePoint::on_sphere(radius, FRAC_PI_2 + FRAC_PI_2 * ring as f32 / rings as f32, -height / 2.));
+                mesh_builder.push_lathe(&top_hemisphere.chain(bottom_hemisphere).collect::<Vec<_>>(), segments);
+            }
+        }
+        mesh_builder.build(self.mesh_name())
+    }
+}
+
+// a point of the 2d profile that gets revolved around the y axis, normal is (radial, y)
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: Vec2,
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: Vec2) -> Self {
+        Self {
+            radius,
+            y,
+            normal
+        }
+    }
+
+    // polar_angle 0 is the north pole
+    fn on_sphere(radius: f32, polar_angle: f32, y_offset: f32) -> Self {
+        let normal = Vec2::new(polar_angle.sin(), polar_angle.cos());
+        // exactly 0 at the poles, so their triangles are recognized as fans
+        let ring_radius = if normal.x.abs() < 1e-6 { 0. } else { radius * normal.x };
+        Self::new(ring_radius, y_offset + radius * normal.y, normal)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MeshBuilder {
+    data: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn push_vertex(&mut self, position: Vec3, normal: Vec3, uv: [f32; 2]) -> u32 {
+        self.data.push(Vertex::new(position.to_array(), normal.to_array(), uv, [1., 0., 0., 1.]));
+        (self.data.len() - 1) as u32
+    }
+
+    // counter clockwise seen from the side the normals point to
+    fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    // revolves the profile around the y axis, the profile has to go down on the side its normals point away from (e.g. north to south pole).
+    // points on the axis become triangle fans, the first and last column are at the same angle but have their own vertices for the uv seam
+    fn push_lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let segments = segments.max(3);
+        let first_vertex = self.data.len() as u32;
+        for (row, profile_point) in profile.iter().enumerate() {
+            let v = row as f32 / (profile.len() - 1) as f32;
+            for column in 0..=segments {
+                let u = column as f32 / segments as f32;
+                let (sin, cos) = (TAU * u).sin_cos();
+                let position = Vec3::new(profile_point.radius * cos, profile_point.y, profile_point.radius * sin);
+                let normal = Vec3::new(profile_point.normal.x * cos, profile_point.normal.y, profile_point.normal.x * sin).normalize();
+                self.push_vertex(position, normal, [u, v]);
+            }
+        }
+        let vertex_index = |row: usize, column: u32| first_vertex + row as u32 * (segments + 1) + column;
+        for row in 0..profile.len() - 1 {
+            for column in 0..segments {
+                let (a, b) = (vertex_index(row, column), vertex_index(row + 1, column));
+                let (c, d) = (vertex_index(row + 1, column + 1), vertex_index(row, column + 1));
+                if profile[row].radius != 0. {
+                    self.push_triangle(a, d, c);
+                }
+                if profile[row + 1].radius != 0. {
+                    self.push_triangle(a, c, b);
+                }
+            }
+        }
+    }
+
+    fn push_plane(&mut self, width: f32, depth: f32, segments_x: u32, segments_z: u32) {
+        let (segments_x, segments_z) = (segments_x.max(1), segments_z.max(1));
+        let first_vertex = self.data.len() as u32;
+        for row in 0..=segments_z {
+            let v = row as f32 / segments_z as f32;
+            for column in 0..=segments_x {
+                let u = column as f32 / segments_x as f32;
+                self.push_vertex(Vec3::new((u - 0.5) * width, 0., (v - 0.5) * depth), Vec3::Y, [u, v]);
+            }
+        }
+        let vertex_index = |row: u32, column: u32| first_vertex + row * (segments_x + 1) + column;
+        for row in 0..segments_z {
+            for column in 0..segments_x {
+                let (a, b) = (vertex_index(row, column), vertex_index(row, column + 1));
+                let (c, d) = (vertex_index(row + 1, column + 1), vertex_index(row + 1, column));
+                self.push_triangle(a, d, c);
+                self.push_triangle(a, c, b);
+            }
+        }
+    }
+
+    fn push_icosphere(&mut self, radius: f32, subdivisions: u32) {
+        let t = (1. + 5_f32.sqrt()) / 2.;
+        let mut positions: Vec<Vec3> = [
+            (-1., t, 0.), (1., t, 0.), (-1., -t, 0.), (1., -t, 0.),
+            (0., -1., t), (0., 1., t), (0., -1., -t), (0., 1., -t),
+            (t, 0., -1.), (t, 0., 1.), (-t, 0., -1.), (-t, 0., 1.),
+        ].map(|(x, y, z)| Vec3::new(x, y, z).normalize()).to_vec();
+        let mut triangles: Vec<[usize; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+        for _ in 0..subdivisions {
+            // edges are shared by two triangles, their midpoint must only be created once
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut midpoint = |a: usize, b: usize, positions: &mut Vec<Vec3>| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push(((positions[a] + positions[b]) / 2.).normalize());
+                positions.len() - 1
+            });
+            triangles = triangles.into_iter().flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b, &mut positions), midpoint(b, c, &mut positions), midpoint(c, a, &mut positions));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            }).collect();
+        }
+
+        // spherical uvs, triangles crossing the seam get their own vertices with u continued past 1
+        let first_vertex = self.data.len() as u32;
+        let uvs: Vec<[f32; 2]> = positions.iter().map(|position| [0.5 + position.z.atan2(position.x) / TAU, position.y.acos() / PI]).collect();
+        for (position, uv) in positions.iter().zip(uvs.iter()) {
+            self.push_vertex(*position * radius, *position, *uv);
+        }
+        for triangle in triangles {
+            let max_u = triangle.iter().map(|index| uvs[*index][0]).fold(f32::MIN, f32::max);
+            let [a, b, c] = triangle.map(|index| match max_u - uvs[index][0] > 0.5 {
+                true => self.push_vertex(positions[index] * radius, positions[index], [uvs[index][0] + 1., uvs[index][1]]),
+                false => first_vertex + index as u32,
+            });
+            self.push_triangle(a, b, c);
+        }
+    }
+
+    fn build(self, name: String) -> Mesh {
+        let mut mesh = Mesh::new(self.data, self.indices, name);
+        mesh.recalculate_tangents();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(mesh: &Mesh) -> Vec<Vec3> {
+        mesh.indices.iter().map(|index| Vec3::from(mesh.data[*index as usize].position)).collect()
+    }
+
+    // welds vertices by position (uv seams and hard edges have duplicates), then every edge has to be used once in each direction
+    fn assert_closed_manifold(mesh: &Mesh, euler_characteristic: i64) {
+        let mut welded: HashMap<[i64; 3], usize> = HashMap::new();
+        let welded_indices: Vec<usize> = positions(mesh).iter().map(|position| {
+            let key = position.to_array().map(|coordinate| (coordinate * 1e4).round() as i64);
+            let next_index = welded.len();
+            *welded.entry(key).or_insert(next_index)
+        }).collect();
+        let mut directed_edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for triangle in welded_indices.chunks_exact(3) {
+            assert!(triangle[0] != triangle[1] && triangle[1] != triangle[2] && triangle[2] != triangle[0], "degenerate triangle in {}", mesh.name);
+            for (a, b) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])] {
+                *directed_edges.entry((a, b)).or_default() += 1;
+            }
+        }
+        for ((a, b), count) in directed_edges.iter() {
+            assert_eq!(*count, 1, "edge used twice in the same direction in {}", mesh.name);
+            assert!(directed_edges.contains_key(&(*b, *a)), "open edge in {}", mesh.name);
+        }
+        let (vertices, edges, faces) = (welded.len() as i64, directed_edges.len() as i64 / 2, mesh.indices.len() as i64 / 3);
+        assert_eq!(vertices - edges + faces, euler_characteristic, "{}", mesh.name);
+    }
+
+    // counter clockwise seen from outside: the face normals agree with the vertex normals and the enclosed volume is positive
+    fn assert_outward_winding(mesh: &Mesh) {
+        let mut signed_volume = 0.;
+        for (triangle_positions, triangle_indices) in positions(mesh).chunks_exact(3).zip(mesh.indices.chunks_exact(3)) {
+            let [a, b, c] = [triangle_positions[0], triangle_positions[1], triangle_positions[2]];
+            let face_normal = (b - a).cross(c - a);
+            for index in triangle_indices {
+                assert!(face_normal.dot(Vec3::from(mesh.data[*index as usize].normal)) > 0., "inward facing triangle in {}", mesh.name);
+            }
+            signed_volume += a.dot(b.cross(c)) / 6.;
+        }
+        assert!(signed_volume > 0., "{}", mesh.name);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        let mesh = Primitive::UvSphere { radius: 0.5, segments: 16, rings: 8 }.mesh();
+        assert_eq!(mesh.data.len(), 9 * 17);
+        assert_eq!(mesh.indices.len(), 3 * 2 * 16 * 7);
+        assert!(mesh.data.iter().all(|vertex| (Vec3::from(vertex.position).length() - 0.5).abs() < 1e-5));
+        assert_closed_manifold(&mesh, 2);
+        assert_outward_winding(&mesh);
+    }
+
+    #[test]
+    fn icosphere() {
+        let mesh = Primitive::Icosphere { radius: 2., subdivisions: 2 }.mesh();
+        assert_eq!(mesh.indices.len(), 3 * 20 * 16);
+        // 10 * 4^n + 2 distinct positions, plus the seam duplicates
+        assert!(mesh.data.len() >= 162);
+        assert!(mesh.data.iter().all(|vertex| (Vec3::from(vertex.position).length() - 2.).abs() < 1e-5));
+        assert_closed_manifold(&mesh, 2);
+        assert_outward_winding(&mesh);
+    }
+
+    #[test]
+    fn plane() {
+        let mesh = Primitive::Plane { width: 2., depth: 1., segments_x: 4, segments_z: 2 }.mesh();
+        assert_eq!(mesh.data.len(), 5 * 3);
+        assert_eq!(mesh.indices.len(), 6 * 4 * 2);
+        for triangle in positions(&mesh).chunks_exact(3) {
+            assert!((triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).dot(Vec3::Y) > 0.);
+        }
+    }
+
+    #[test]
+    fn cylinder() {
+        let mesh = Primitive::Cylinder { radius: 0.5, height: 2., segments: 12 }.mesh();
+        assert_eq!(mesh.data.len(), 3 * 2 * 13);
+        assert_eq!(mesh.indices.len(), 3 * 4 * 12);
+        assert_closed_manifold(&mesh, 2);
+        assert_outward_winding(&mesh);
+    }
+
+    #[test]
+    fn cone() {
+        let mesh = Primitive::Cone { radius: 0.5, height: 1., segments: 12 }.mesh();
+        assert_eq!(mesh.data.len(), 2 * 2 * 13);
+        assert_eq!(mesh.indices.len(), 3 * 2 * 12);
+        assert_closed_manifold(&mesh, 2);
+        assert_outward_winding(&mesh);
+    }
+
+    #[test]
+    fn torus() {
+        let mesh = Primitive::Torus { major_radius: 1., minor_radius: 0.25, major_segments: 24, minor_segments: 12 }.mesh();
+        assert_eq!(mesh.data.len(), 13 * 25);
+        assert_eq!(mesh.indices.len(), 3 * 2 * 24 * 12);
+        assert_closed_manifold(&mesh, 0);
+        assert_outward_winding(&mesh);
+    }
+
+    #[test]
+    fn capsule() {
+        let mesh = Primitive::Capsule { radius: 0.25, height: 1., segments: 16, rings: 4 }.mesh();
+        assert_eq!(mesh.data.len(), 2 * 5 * 17);
+        assert_eq!(mesh.indices.len(), 3 * 4 * 16 * 4);
+        assert!(mesh.data.iter().all(|vertex| vertex.position[1].abs() <= 0.75 + 1e-5));
+        assert_closed_manifold(&mesh, 2);
+        assert_outward_winding(&mesh);
+    }
+}
@@ -1,39 +1,56 @@
-use std::{sync::Arc};
+use std::{error::Error, path::Path, sync::Arc};
 
-use vulkano::{command_buffer::{CommandBufferExecFuture, SecondaryAutoCommandBuffer}, device::{physical::{PhysicalDevice, PhysicalDeviceType}, Device, DeviceCreateInfo, 
-DeviceExtensions, Queue, QueueCreateInfo, QueueFlags}, image::{Image, ImageUsage}, instance::Instance, ordered_passes_renderpass, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, 
-    ColorBlendState}, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule, single_pass_renderpass, swapchain::{PresentFuture, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainPresentInfo}, sync::{future::{FenceSignalFuture, JoinFuture}, GpuFuture}, Validated, ValidationError, VulkanError};
+use image::RgbaImage;
+use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage}, command_buffer::{AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, CopyImageToBufferInfo, SecondaryAutoCommandBuffer}, device::{physical::{PhysicalDevice, PhysicalDeviceType}, Device, DeviceCreateInfo, 
+DeviceExtensions, Queue, QueueCreateInfo, QueueFlags}, format::Format, image::{Image, ImageCreateInfo, ImageType, ImageUsage}, instance::Instance, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}, ordered_passes_renderpass, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, 
+    ColorBlendState}, depth_stencil::{DepthState, DepthStencilState}, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::ViewportState, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule, single_pass_renderpass, swapchain::{PresentFuture, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainPresentInfo}, sync::{self, future::{FenceSignalFuture, JoinFuture}, GpuFuture}, ValidationError};
 use winit::{event_loop::{EventLoop}, window::{Window, WindowBuilder}};
 
-use crate::{engine::scene::Scene, initialize::vulkan_instancing::get_vulkan_instance, physics::physics_traits::Transform};
+use crate::{engine::{camera::Camera, entity_id::EntityId, scene::Scene}, initialize::vulkan_instancing::{get_headless_vulkan_instance, get_vulkan_instance}, physics::physics_traits::Transform};
 
-use super::{buffer_manager::BufferManager, primitives::{self, Mesh}, rendering_traits::{Visibility}, shaders::Shaders};
+use super::{buffer_manager::BufferManager, frame::DEPTH_FORMAT, primitives::{self, Mesh}, rendering_traits::{Visibility}, shaders::Shaders, transform_buffers::TransformBufferUsage};
 
 pub enum EntityUpdateInfo {
     HasMoved(HasMovedInfo),
-    ChangedVisibility(Visibility)
+    ChangedVisibility(ChangedVisibilityInfo)
 }
 
 pub struct HasMovedInfo {
-    pub entity_id: usize,
+    pub entity_id: EntityId,
     pub new_transform: Transform
 }
 
+pub struct ChangedVisibilityInfo {
+    pub entity_id: EntityId,
+    pub new_visibility: Visibility
+}
+
 pub enum EngineEvent {
-    EntityAdded(Transform, Mesh, usize),
+    // the mesh is shared with the engine's mesh registry, it only gets copied if the renderer doesn't have it loaded yet
+    EntityAdded(Transform, Arc<Mesh>, EntityId),
+    EntityRemoved(EntityId),
     EntitiesUpdated(Vec<EntityUpdateInfo>),
     ChangedActiveScene(Arc<Scene>),
+    // the active scene's camera moved (e.g. by a camera controller), the rest of the scene stays
+    ChangedActiveCamera(Camera),
+    // sent after a scene got unloaded, see Renderer::unload_unused_meshes for the flag
+    UnloadUnusedMeshes(bool),
 }
 
+// number of offscreen images a headless renderer cycles through, mirrors the usual swapchain image count
+pub const HEADLESS_FRAMES_IN_FLIGHT: usize = 3;
+const HEADLESS_IMAGE_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
 pub struct Renderer {
     vulkan_instance: Arc<Instance>,
-    window: Arc<Window>, 
-    pub surface: Arc<Surface>,
+    window: Option<Arc<Window>>, 
+    pub surface: Option<Arc<Surface>>,
     physical_device: Arc<PhysicalDevice>,
     pub device: Arc<Device>,
     queue_family_index: u32,
     pub queue: Arc<Queue>,
-    pub swapchain: Arc<Swapchain>,
+    pub transfer_queue: Option<Arc<Queue>>,
+    pub swapchain: Option<Arc<Swapchain>>,
     vertex_shader: Arc<ShaderModule>,
     fragment_shader: Arc<ShaderModule>,
     pub buffer_manager: BufferManager,
@@ -55,24 +72,63 @@ impl Renderer {
         let window = Arc::new(WindowBuilder::new().build(&event_loop).unwrap());
         let surface = Surface::from_window(vulkan_instance.clone(), window.clone()).unwrap();
         let (physical_device, queue_family_index) = Renderer::build_physical_device_and_queue_family_index(vulkan_instance.clone(), surface.clone(), &device_extensions);
-        let (queue, device) = Renderer::build_device_and_queues(physical_device.clone(), queue_family_index, device_extensions);
+        let (queue, device, transfer_queue) = Renderer::build_device_and_queues(physical_device.clone(), queue_family_index, device_extensions);
         let (swapchain, swapchain_images) = Renderer::build_swapchain_and_swapchain_images(physical_device.clone(), surface.clone(), window.clone(), device.clone());
-        let render_pass = Renderer::build_render_pass(device.clone(), swapchain.clone());
+        let render_pass = Renderer::build_render_pass(device.clone(), swapchain.image_format());
+        let (vertex_shader, fragment_shader) = Renderer::build_shaders(device.clone());
+        let graphics_pipeline = Renderer::build_pipeline(vertex_shader.clone(), fragment_shader.clone(), device.clone(), render_pass.clone());
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let buffer_manager = BufferManager::new(device.clone(), memory_allocator, graphics_pipeline.clone(), swapchain_images, render_pass.clone(), queue_family_index, transfer_queue.as_ref().map(|transfer_queue| transfer_queue.queue_family_index()));
+        let active_scene = Arc::new(Scene::new());
+        let currenty_not_displayed_swapchain_image_index = 0;
+
+        Renderer {
+            vulkan_instance,
+            window: Some(window),
+            physical_device,
+            queue_family_index,
+            queue,
+            transfer_queue,
+            device,
+            surface: Some(surface),
+            swapchain: Some(swapchain),
+            buffer_manager,
+            vertex_shader,
+            fragment_shader,
+            render_pass,
+            graphics_pipeline,
+            active_scene,
+            currenty_not_displayed_swapchain_image_index
+        }
+
+    }
+
+    // renders into offscreen images instead of a swapchain, so no window, surface or display is needed (works with software ICDs like lavapipe)
+    pub fn new_headless(image_extent: [u32; 2]) -> Renderer {
+        let device_extensions = DeviceExtensions::empty();
+
+        let vulkan_instance = get_headless_vulkan_instance();
+        let (physical_device, queue_family_index) = Renderer::build_headless_physical_device_and_queue_family_index(vulkan_instance.clone(), &device_extensions);
+        let (queue, device, transfer_queue) = Renderer::build_device_and_queues(physical_device.clone(), queue_family_index, device_extensions);
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let offscreen_images = Renderer::build_offscreen_images(memory_allocator.clone(), image_extent, HEADLESS_FRAMES_IN_FLIGHT);
+        let render_pass = Renderer::build_render_pass(device.clone(), HEADLESS_IMAGE_FORMAT);
         let (vertex_shader, fragment_shader) = Renderer::build_shaders(device.clone());
-        let graphics_pipeline = Renderer::build_pipeline(vertex_shader.clone(), fragment_shader.clone(), device.clone(), render_pass.clone(), None);
-        let buffer_manager = BufferManager::new(device.clone(), graphics_pipeline.clone(), swapchain_images, render_pass.clone(), queue_family_index);
+        let graphics_pipeline = Renderer::build_pipeline(vertex_shader.clone(), fragment_shader.clone(), device.clone(), render_pass.clone());
+        let buffer_manager = BufferManager::new(device.clone(), memory_allocator, graphics_pipeline.clone(), offscreen_images, render_pass.clone(), queue_family_index, transfer_queue.as_ref().map(|transfer_queue| transfer_queue.queue_family_index()));
         let active_scene = Arc::new(Scene::new());
         let currenty_not_displayed_swapchain_image_index = 0;
 
         Renderer {
             vulkan_instance,
-            window,
+            window: None,
             physical_device,
             queue_family_index,
             queue,
+            transfer_queue,
             device,
-            surface,
-            swapchain,
+            surface: None,
+            swapchain: None,
             buffer_manager,
             vertex_shader,
             fragment_shader,
@@ -81,7 +137,27 @@ impl Renderer {
             active_scene,
             currenty_not_displayed_swapchain_image_index
         }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.swapchain.is_none()
+    }
 
+    // how many transforms are in use and how many fit before the transform buffers grow
+    pub fn transform_buffer_usage(&self) -> TransformBufferUsage {
+        self.buffer_manager.transform_buffers.borrow().usage()
+    }
+
+    // frees the geometry of meshes no entity uses anymore, e.g. after unloading a level. compact afterwards merges the freed space
+    pub fn unload_unused_meshes(&mut self, compact: bool) {
+        let unloaded_meshes = self.buffer_manager.vertex_buffer.unload_unused_meshes();
+        if compact && !unloaded_meshes.is_empty() {
+            self.buffer_manager.vertex_buffer.compact();
+        }
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.buffer_manager.frames.len()
     }
 
     pub fn build_physical_device_and_queue_family_index(instance: Arc<Instance>, surface: Arc<Surface>, device_extensions: &DeviceExtensions) -> (Arc<PhysicalDevice>, u32) {
@@ -118,22 +194,57 @@ impl Renderer {
         (physical_device, queue_family_index)
     }
 
-    pub fn build_device_and_queues(physical_device: Arc<PhysicalDevice>, queue_family_index: u32, device_extensions: DeviceExtensions,) -> (Arc<Queue>, Arc<Device>) {
+    pub fn build_headless_physical_device_and_queue_family_index(instance: Arc<Instance>, device_extensions: &DeviceExtensions) -> (Arc<PhysicalDevice>, u32) {
+        instance
+            .enumerate_physical_devices()
+            .expect("failed to enumerate physical devices")
+            .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .filter_map(|p| {
+                p.queue_family_properties()
+                    .iter()
+                    .position(|q| q.queue_flags.contains(QueueFlags::GRAPHICS))
+                    .map(|q| (p, q as u32))
+            })
+            .min_by_key(|(p, _)| match p.properties().device_type {
+                PhysicalDeviceType::DiscreteGpu => 0,
+                PhysicalDeviceType::IntegratedGpu => 1,
+                PhysicalDeviceType::VirtualGpu => 2,
+                PhysicalDeviceType::Cpu => 3,
+                _ => 4,
+            })
+            .expect("no device available")
+    }
+
+    // besides the graphics queue, a queue of a transfer only family is created if the device has one (usually backed by dma engines), geometry uploads then run on it
+    pub fn build_device_and_queues(physical_device: Arc<PhysicalDevice>, queue_family_index: u32, device_extensions: DeviceExtensions,) -> (Arc<Queue>, Arc<Device>, Option<Arc<Queue>>) {
+        let transfer_queue_family_index = physical_device.queue_family_properties().iter()
+            .position(|queue_family_properties| {
+                queue_family_properties.queue_flags.contains(QueueFlags::TRANSFER) && !queue_family_properties.queue_flags.intersects(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+            })
+            .map(|transfer_queue_family_index| transfer_queue_family_index as u32);
+        let mut queue_create_infos = vec![QueueCreateInfo {
+            queue_family_index,
+            ..Default::default()
+        }];
+        if let Some(transfer_queue_family_index) = transfer_queue_family_index {
+            queue_create_infos.push(QueueCreateInfo {
+                queue_family_index: transfer_queue_family_index,
+                ..Default::default()
+            });
+        }
         let (device, mut queues) = Device::new(
             physical_device.clone(),
             DeviceCreateInfo {
-                // here we pass the desired queue family to use by index
-                queue_create_infos: vec![QueueCreateInfo {
-                    queue_family_index,
-                    ..Default::default()
-                }],
+                // here we pass the desired queue families to use by index
+                queue_create_infos,
                 enabled_extensions: device_extensions,
                 ..Default::default()
             },
         )
         .expect("failed to create device");
         let queue: Arc<Queue> = queues.next().unwrap();
-        (queue, device)
+        let transfer_queue = queues.next();
+        (queue, device, transfer_queue)
     }
 
     pub fn build_swapchain_and_swapchain_images(physical_device: Arc<PhysicalDevice>, surface: Arc<Surface>, window: Arc<Window>, device: Arc<Device>) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
@@ -162,20 +273,47 @@ impl Renderer {
         (swapchain, swapchain_images)
     }
 
-    pub fn build_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
+    pub fn build_offscreen_images(memory_allocator: Arc<StandardMemoryAllocator>, image_extent: [u32; 2], image_count: usize) -> Vec<Arc<Image>> {
+        (0..image_count).map(|_| {
+            Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: HEADLESS_IMAGE_FORMAT,
+                    extent: [image_extent[0], image_extent[1], 1],
+                    usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        })
+        .collect()
+    }
+
+    pub fn build_render_pass(device: Arc<Device>, image_format: Format) -> Arc<RenderPass> {
         let render_pass = ordered_passes_renderpass!(
             device.clone(),
             attachments: {
                 // `foo` is a custom name we give to the first and only attachment.
                 color: {
-                    format: swapchain.image_format(),  // set the format the same as the swapchain
+                    format: image_format,  // set the format the same as the swapchain (or offscreen) images
                     samples: 1,
                     load_op: Clear,
                     store_op: Store,
                 },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
             },
             passes: [
-                    { color: [color], depth_stencil: {}, input: [] },
+                    { color: [color], depth_stencil: {depth}, input: [] },
                     { color: [color], depth_stencil: {}, input: [] },
                 ],
         )
@@ -190,17 +328,8 @@ impl Renderer {
         (shaders.vertex_shader, shaders.fragment_shader)
     }
 
-    pub fn build_pipeline(vertex_shader: Arc<ShaderModule>, fragment_shader: Arc<ShaderModule>, device: Arc<Device>, render_pass: Arc<RenderPass>, viewport: Option<Viewport>) -> Arc<GraphicsPipeline> {
-        let viewport = match viewport {
-            Some(viewport) => viewport,
-            None => {
-                Viewport {
-                    offset: [0.0, 0.0],
-                    extent: [1024.0, 1024.0],
-                    depth_range: 0.0..=1.0,
-                }
-            }
-        };
+    // the viewport is dynamic state set per recorded frame, so the pipeline survives swapchain recreation
+    pub fn build_pipeline(vertex_shader: Arc<ShaderModule>, fragment_shader: Arc<ShaderModule>, device: Arc<Device>, render_pass: Arc<RenderPass>) -> Arc<GraphicsPipeline> {
         // A Vulkan shader can in theory contain multiple entry points, so we have to specify
         // which one.
         let vs = vertex_shader.entry_point("main").unwrap();
@@ -235,13 +364,16 @@ impl Renderer {
                 vertex_input_state: Some(vertex_input_state),
                 // Indicate the type of the primitives (the default is a list of triangles).
                 input_assembly_state: Some(InputAssemblyState::default()),
-                // Set the fixed viewport.
-                viewport_state: Some(ViewportState {
-                    viewports: [viewport].into_iter().collect(),
-                    ..Default::default()
-                }),
+                // One viewport, its extent is set when recording the frame.
+                viewport_state: Some(ViewportState::default()),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
                 // Ignore these for now.
                 rasterization_state: Some(RasterizationState::default()),
+                // Closer fragments win, regardless of the order the meshes are drawn in.
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
                 multisample_state: Some(MultisampleState::default()),
                 color_blend_state: Some(ColorBlendState::with_attachment_states(
                     subpass.num_color_attachments(),
@@ -257,45 +389,105 @@ impl Renderer {
         pipeline
     }
 
-    pub fn get_future(& mut self, previous_future: Box<dyn GpuFuture>, acquire_future: SwapchainAcquireFuture, acquired_swapchain_index: usize, gui_command_buffer: Arc<SecondaryAutoCommandBuffer>) -> Result<FenceSignalFuture<PresentFuture<CommandBufferExecFuture<JoinFuture<Box<dyn GpuFuture>, SwapchainAcquireFuture>>>>, Validated<VulkanError>>  {
+    pub fn get_future(& mut self, previous_future: Box<dyn GpuFuture>, acquire_future: SwapchainAcquireFuture, acquired_swapchain_index: usize, gui_command_buffer: Arc<SecondaryAutoCommandBuffer>) -> Result<FenceSignalFuture<PresentFuture<CommandBufferExecFuture<Box<dyn GpuFuture>>>>, Box<dyn Error>>  {
         //let after_future = gui.draw_on_image(previous_future, self.frames[acquired_swapchain_index].swapchain_image_view.clone());
         //println!("acquired_swapchain_index: {}", acquired_swapchain_index);
-        let command_buffer = self.buffer_manager.build_command_buffer(acquired_swapchain_index, gui_command_buffer);
-        previous_future
-            .join(acquire_future)
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
+        let command_buffer = self.buffer_manager.build_command_buffer(acquired_swapchain_index, Some(gui_command_buffer));
+        let after_geometry_uploads = self.then_execute_geometry_uploads(previous_future.join(acquire_future).boxed())?;
+        let future = after_geometry_uploads
+            .then_execute(self.queue.clone(), command_buffer)?
             .then_swapchain_present(
                 self.queue.clone(),
-                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), acquired_swapchain_index.try_into().unwrap())
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone().unwrap(), acquired_swapchain_index.try_into().unwrap())
             )
-            .then_signal_fence_and_flush()
+            .then_signal_fence_and_flush()?;
+        Ok(future)
+    }
+
+    // with a dedicated transfer queue the pending geometry uploads get their own submission, the frame waits for it with a semaphore.
+    // without one they are recorded into the frame's command buffer instead
+    fn then_execute_geometry_uploads(&mut self, previous_future: Box<dyn GpuFuture>) -> Result<Box<dyn GpuFuture>, Box<dyn Error>> {
+        let (Some(transfer_queue), Some(transfer_command_buffer)) = (self.transfer_queue.clone(), self.buffer_manager.build_transfer_command_buffer()?) else {
+            return Ok(previous_future);
+        };
+        Ok(previous_future
+            .then_execute(transfer_queue, transfer_command_buffer)?
+            .then_signal_semaphore()
+            .boxed())
+    }
+
+    // headless counterpart of get_future, records the frame and blocks until the gpu is done with it
+    pub fn render_offscreen_frame(&mut self, frame_index: usize) -> Result<(), Box<dyn Error>> {
+        let command_buffer = self.buffer_manager.build_command_buffer(frame_index, None);
+        self.then_execute_geometry_uploads(sync::now(self.device.clone()).boxed())?
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+        self.currenty_not_displayed_swapchain_image_index = (frame_index + 1) % self.frames_in_flight();
+        Ok(())
+    }
+
+    // reads the color attachment of the given frame back to the host, expects the frame to be finished rendering
+    pub fn capture_frame(&self, frame_index: usize) -> Result<RgbaImage, Box<dyn Error>> {
+        let image = self.buffer_manager.frames[frame_index].swapchain_image.clone();
+        if image.format() != HEADLESS_IMAGE_FORMAT {
+            return Err(format!("can only capture frames with format {:?}, got {:?}", HEADLESS_IMAGE_FORMAT, image.format()).into());
+        }
+        let [width, height, _] = image.extent();
+        let readback_buffer = Buffer::from_iter(
+            self.buffer_manager.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (0..width * height * 4).map(|_| 0u8),
+        )?;
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.buffer_manager.command_buffer_allocator,
+            self.queue_family_index,
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        command_buffer_builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, readback_buffer.clone()))?;
+        let command_buffer = command_buffer_builder.build()?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        let pixels = readback_buffer.read()?.to_vec();
+        RgbaImage::from_raw(width, height, pixels).ok_or_else(|| "readback buffer does not match the image extent".into())
+    }
+
+    pub fn save_frame_png(&self, frame_index: usize, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.capture_frame(frame_index)?.save(path)?;
+        Ok(())
     }
 
     pub fn entities_updated_handler(&mut self, updated_entities_infos: Vec<EntityUpdateInfo>) -> ()  {
-        println!("Got into entitited updated handler");
-        for (i, entity_update_info) in updated_entities_infos.iter().enumerate() {
+        for entity_update_info in updated_entities_infos.iter() {
             match entity_update_info {
                 EntityUpdateInfo::HasMoved(has_moved_info) => {
-                    let mut entity_model_matrices = Vec::new();
-                    let mut last_index = 0;
-                    if has_moved_info.entity_id - last_index > 1 { 
-                        self.buffer_manager.copy_transform_data_slice_to_buffer(0, entity_model_matrices.len(), &entity_model_matrices, self.currenty_not_displayed_swapchain_image_index);
-                        entity_model_matrices.clear();
+                    if let Err(err) = self.buffer_manager.update_entity_transform_buffer(has_moved_info.entity_id, &has_moved_info.new_transform) {
+                        println!("something went wrong while updating the transform of entity {:?}: {err}", has_moved_info.entity_id);
+                    }
+                },
+                EntityUpdateInfo::ChangedVisibility(changed_visibility_info) => {
+                    if !self.buffer_manager.vertex_buffer.mesh_accessor.set_entity_visibility(changed_visibility_info.entity_id, changed_visibility_info.new_visibility) {
+                        println!("something went wrong while changing the visibility of entity {:?}: it has no mesh", changed_visibility_info.entity_id);
                     }
-                    entity_model_matrices.push(has_moved_info.new_transform.model_matrix());
-                    last_index = has_moved_info.entity_id;
-                    
                 },
-                EntityUpdateInfo::ChangedVisibility(changed_visibility_info) => todo!(),
             }
         }
     }
-
-    //todo: make it so that when multiple entities get added in one frame, they will get collected and not as many events get fired
-    pub fn entity_added_handler(&mut self, entity_transform: Transform, entity_mesh: Mesh, entity_index: usize, swapchain_image_index: usize) -> ()  {
+    pub fn entity_added_handler(&mut self, entity_transform: Transform, entity_mesh: Arc<Mesh>, entity_id: EntityId) -> ()  {
         println!("Entity added");
-        match self.buffer_manager.register_entity(entity_transform, entity_mesh, swapchain_image_index, entity_index) {
+        match self.buffer_manager.register_entity(entity_transform, &entity_mesh, entity_id) {
             Ok(()) => {
                 println!("Successfully handled EntityAdded event");
             }
@@ -303,47 +495,78 @@ impl Renderer {
         }
     }
 
+    pub fn entity_removed_handler(&mut self, entity_id: EntityId, swapchain_image_index: usize) -> ()  {
+        if let Err(err) = self.buffer_manager.unregister_entity(entity_id, swapchain_image_index) {
+            println!("something went wrong while handling the EntityRemoved Event: {err}");
+        }
+    }
+
     pub fn changed_active_scene_handler(&mut self, active_scene: Arc<Scene>) -> ()  {
         println!("Active scene changed in frame index: {}", self.currenty_not_displayed_swapchain_image_index);
-        match self.buffer_manager.copy_vp_camera_data(&active_scene.camera, self.currenty_not_displayed_swapchain_image_index) {
+        self.active_scene = active_scene;
+        match self.copy_active_camera_data() {
             Ok(()) => {
                 println!("Successfully handled Changed Active Scene event");
             }
-            Err(err) => println!("something went wrong while handling the EntityAdded Event"),
+            Err(err) => println!("something went wrong while handling the ChangedActiveScene Event: {err}"),
         }
+    }
 
-        //here the buffer_manager would have to do way more after setting the camera matrix, we would have to overwrite the whole state basically.
-        //maybe an idea would be to have 1 buffer manager for each scene
+    pub fn changed_active_camera_handler(&mut self, camera: Camera) -> () {
+        Arc::make_mut(&mut self.active_scene).camera = camera;
+        if let Err(err) = self.copy_active_camera_data() {
+            println!("something went wrong while handling the ChangedActiveCamera Event: {err}");
+        }
     }
 
-    //pub fn recreate_swapchain(&mut self) {
-    //    let new_dimensions = self.window.inner_size();
-    //    let (new_swapchain, new_images) = self.swapchain
-    //        .recreate(SwapchainCreateInfo {
-    //            // Here, `image_extend` will correspond to the window dimensions.
-    //            image_extent: new_dimensions.into(),
-    //            ..self.swapchain.create_info()
-    //        })
-    //        .expect("failed to recreate swapchain: {e}");
-    //   
-    //    // since framebuffers are dependant on swapchain (images) we need to recreate them aswell
-    //    let frames = Renderer::build_frames(self.device.clone(), self.pipeline.clone(), new_images.clone(), 
-    //                                                    self.render_pass.clone(), self.queue_family_index, self.buffer_manager);
-//
-    //    self.swapchain = new_swapchain;
-    //    self.swapchain_images = new_images;
-    //    self.frames = frames;
-    //}
+    // the active scene's camera with its aspect ratio fitted to the images rendered to
+    fn copy_active_camera_data(&mut self) -> Result<(), Box<dyn Error>> {
+        let [width, height] = self.buffer_manager.frame_extent();
+        let mut camera = self.active_scene.camera.clone();
+        camera.set_aspect_ratio(width as f32 / height as f32);
+        self.buffer_manager.set_vp_camera_data(&camera);
+        Ok(())
+    }
 
-    pub fn recreate_pipeline(&mut self) {
-        let new_dimensions = self.window.inner_size();
-        let mut viewport = Viewport {
-            offset: [0.0, 0.0],
-            extent: [1024.0, 1024.0],
-            depth_range: 0.0..=1.0,
-        };
+    // a minimized window can't be presented to
+    pub fn is_minimized(&self) -> bool {
+        match &self.window {
+            Some(window) => {
+                let size = window.inner_size();
+                size.width == 0 || size.height == 0
+            }
+            None => false,
+        }
+    }
 
-        viewport.extent = new_dimensions.into();
+    // rebuilds the swapchain, frames and camera aspect ratio for the current window size.
+    // returns false if that wasn't possible (e.g. while the window is minimized), the caller should try again next frame
+    pub fn recreate_swapchain(&mut self) -> bool {
+        let (Some(window), Some(swapchain)) = (&self.window, &self.swapchain) else { return false };
+        if self.is_minimized() {
+            return false;
+        }
+        let new_dimensions = window.inner_size();
+        let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
+            // Here, `image_extend` will correspond to the window dimensions.
+            image_extent: new_dimensions.into(),
+            ..swapchain.create_info()
+        }) {
+            Ok(recreated) => recreated,
+            // the window can be resized again while recreating, e.g. while the user drags its border
+            Err(err) => {
+                println!("failed to recreate swapchain: {err}");
+                return false;
+            }
+        };
+       
+        // since framebuffers and depth images are dependant on swapchain (images) we need to recreate them aswell
+        self.buffer_manager.recreate_frames(new_images, self.render_pass.clone());
+        self.swapchain = Some(new_swapchain);
+        if let Err(err) = self.copy_active_camera_data() {
+            println!("failed to update the camera aspect ratio: {err}");
+        }
+        true
     }
 
     //fn synch_buffers_handler(&mut self, most_up_to_date_buffer_index: usize, entity: Arc<dyn RenderableEntity>) -> () {
@@ -1,12 +1,13 @@
-use crate::{engine::general_traits::Entity, physics::physics_traits::HasTransform};
+use crate::engine::general_traits::Entity;
 
 use super::primitives::{Triangle, Mesh};
 
-pub trait HasMesh : Entity  {
+pub trait HasMesh {
     fn get_mesh(& mut self, name: String) -> Mesh;
     fn get_data(& self) -> Vec<Triangle>;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Visibility {
     Visible,
     Invisible
@@ -15,4 +16,3 @@ pub enum Visibility {
 pub trait UpdateGraphics : Entity {
     fn update_graphics(& self, swapchain_image_index: usize) -> ();
 }
-pub trait RenderableEntity : Entity + HasMesh + HasTransform {}
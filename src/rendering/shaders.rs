@@ -9,17 +9,37 @@ mod vertex_shader {
             #version 460
 
             layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+            layout(location = 3) in vec4 tangent;
+            layout(location = 4) in vec4 color;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec4 v_tangent;
+            layout(location = 3) out vec4 v_color;
 
             layout(set = 0, binding = 0) uniform UniformBufferObject {
                 mat4 u_projection_view_matrix;
             } ubo;
 
-            layout(set = 1, binding = 0) uniform TransformBufferObject {
-                mat4 u_transform_matrix[1000000000];
+            layout(set = 1, binding = 0) readonly buffer TransformBufferObject {
+                mat4 u_transform_matrix[];
             } tbo;
+
+            // instance -> transform slot, every mesh's instances are a contiguous range of it
+            layout(set = 1, binding = 1) readonly buffer InstanceBufferObject {
+                uint u_transform_index[];
+            } ibo;
             
             void main() {
-                gl_Position = ubo.u_projection_view_matrix * tbo.u_transform_matrix[gl_InstanceIndex] * vec4(position, 1.0);
+                mat4 model_matrix = tbo.u_transform_matrix[ibo.u_transform_index[gl_InstanceIndex]];
+                mat3 normal_matrix = transpose(inverse(mat3(model_matrix)));
+                v_normal = normalize(normal_matrix * normal);
+                v_uv = uv;
+                v_tangent = vec4(normalize(mat3(model_matrix) * tangent.xyz), tangent.w);
+                v_color = color;
+                gl_Position = ubo.u_projection_view_matrix * model_matrix * vec4(position, 1.0);
             }",
     }
 }
@@ -29,9 +49,21 @@ mod fragment_shader {
         ty: "fragment",
         src: "
             #version 450
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+            layout(location = 2) in vec4 v_tangent;
+            layout(location = 3) in vec4 v_color;
+
             layout(location = 0) out vec4 f_color;
+
+            const vec3 LIGHT_DIRECTION = normalize(vec3(-0.4, -1.0, 0.6));
+            const vec3 BASE_COLOR = vec3(1.0, 0.0, 0.0);
+            const float AMBIENT = 0.2;
+
             void main() {
-                f_color = vec4(1.0, 0.0, 0.0, 1.0);
+                float diffuse = max(dot(normalize(v_normal), -LIGHT_DIRECTION), 0.0);
+                vec3 color = BASE_COLOR * v_color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse);
+                f_color = vec4(color, v_color.a);
             }"
     }
 }
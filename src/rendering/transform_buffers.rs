@@ -1,111 +1,168 @@
-use std::{error::Error, ops::Index, sync::Arc};
+use std::{error::Error, ops::{Index, Range}, sync::Arc};
 
 use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}};
 
-use crate::physics::physics_traits::Transform;
+use crate::{engine::entity_id::{EntityId, EntityMap}, physics::physics_traits::Transform};
 
+use super::free_list_allocator::grown_capacity;
 
+
+// one transform storage buffer per frame in flight, all of them are brought up to date from model_matrices lazily, right before their frame gets recorded
 pub struct TransformBuffers {
+    memory_allocator: Arc<StandardMemoryAllocator>,
     transform_buffers: Vec<Subbuffer<[[[f32; 4]; 4]]>>,
-    entity_to_transform_buffer_index: Vec<usize>,
-    pub newly_added_transform_indexes: Vec<usize>,
+    // number of transforms every buffer has to hold, buffers smaller than this get reallocated on their next flush
+    capacity: usize,
+    // the up to date model matrices, indexed by transform slot
+    model_matrices: Vec<[[f32; 4]; 4]>,
+    // slots that changed since the buffer of the respective frame was last flushed
+    dirty_transform_indexes: Vec<DirtyTransformIndexes>,
+    // transform slot -> entity and entity -> transform slot
+    transform_buffer_index_to_entity: Vec<EntityId>,
+    entity_to_transform_buffer_index: EntityMap<usize>,
 }
 
-pub struct TransformBufferCopyPayload {
-    pub src_buffer: Subbuffer<[[[f32; 4]; 4]]>, 
-    pub target_buffers: Vec<Subbuffer<[[[f32; 4]; 4]]>>, 
-    pub newly_added_transform_indexes: Vec<usize>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransformBufferUsage {
+    pub transform_count: usize,
+    pub capacity: usize,
+    // summed over the buffers of all frames in flight
+    pub allocated_bytes: u64,
 }
 
-const INITIAL_TRANSFORM_BUFFER_SIZE: usize = 2_i32.pow(12) as usize; // 32 instances
+const INITIAL_TRANSFORM_BUFFER_CAPACITY: usize = 2_i32.pow(10) as usize; // 64 KiB per frame in flight
 
 impl TransformBuffers {
     pub fn new(memory_allocator: Arc<StandardMemoryAllocator>, swapchain_images_length: usize) -> Self {
-        let mut transform_buffers = Vec::new();
-        for _ in 0..swapchain_images_length {
-            let transform_initial_data: [[[f32; 4]; 4]; INITIAL_TRANSFORM_BUFFER_SIZE] = [[[0_f32; 4]; 4]; INITIAL_TRANSFORM_BUFFER_SIZE];
-            let uniform_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                transform_initial_data.into_iter()
-            )
-            .unwrap();
-            transform_buffers.push(uniform_buffer);
-        }
+        let capacity = INITIAL_TRANSFORM_BUFFER_CAPACITY;
+        let transform_buffers = (0..swapchain_images_length)
+            .map(|_| Self::allocate_transform_buffer(memory_allocator.clone(), capacity))
+            .collect();
 
-        let entity_to_transform_buffer_index = Vec::new();
-        let newly_added_transform_indexes = Vec::new();
+        let model_matrices = Vec::new();
+        let dirty_transform_indexes = vec![DirtyTransformIndexes::default(); swapchain_images_length];
+        let transform_buffer_index_to_entity = Vec::new();
+        let entity_to_transform_buffer_index = EntityMap::new();
         Self {
+            memory_allocator,
             transform_buffers,
+            capacity,
+            model_matrices,
+            dirty_transform_indexes,
+            transform_buffer_index_to_entity,
             entity_to_transform_buffer_index,
-            newly_added_transform_indexes
         }
     }
 
-    pub fn bind_entity_transform(&mut self, entity_transform: Transform, entity_id: usize, next_swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
-        let entity_transform_index = self.entity_to_transform_buffer_index.len();
-        self.entity_to_transform_buffer_index.push(entity_id);
-        self.newly_added_transform_indexes.push(entity_transform_index);
-        self.copy_transform_data_to_buffer(entity_transform_index, &entity_transform, next_swapchain_image_index)
+    fn allocate_transform_buffer(memory_allocator: Arc<StandardMemoryAllocator>, capacity: usize) -> Subbuffer<[[[f32; 4]; 4]]> {
+        Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            capacity as u64
+        )
+        .unwrap()
     }
 
- // pub fn get_synch_slice(&mut self) -> &[usize] {
- //     self.newly_added_transform_indexes.unwrap().as_slice()
- // }
-
-    pub fn update_entity_transform(& self, entity_transform_index: usize, entity_transform: &Transform, next_swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
-        self.copy_transform_data_to_buffer(entity_transform_index, entity_transform, next_swapchain_image_index)
+    pub fn bind_entity_transform(&mut self, entity_transform: Transform, entity_id: EntityId) -> Result<(), Box<dyn Error>> {
+        let entity_transform_index = self.transform_buffer_index_to_entity.len();
+        if entity_transform_index >= self.capacity {
+            self.capacity = grown_capacity(self.capacity, entity_transform_index + 1);
+        }
+        self.transform_buffer_index_to_entity.push(entity_id);
+        self.entity_to_transform_buffer_index.insert(entity_id, entity_transform_index);
+        self.model_matrices.push(entity_transform.model_matrix());
+        self.mark_dirty(entity_transform_index);
+        Ok(())
     }
 
-    fn copy_transform_data_to_buffer(& self, entity_transform_index: usize, entity_transform: &Transform, next_swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
-        println!("DEBUG");
-        let mut write_lock =  self.transform_buffers[next_swapchain_image_index].write()?;
-        write_lock[entity_transform_index] = entity_transform.model_matrix();
-        //println!("Successfully copied entity transform: {:?} to transform buffer with index: {}", entity_transform.model_matrix(), next_swapchain_image_index);
+    // swap-remove: the last transform moves into the freed slot so the used part of the buffers stays contiguous
+    pub fn unbind_entity_transform(&mut self, entity_id: EntityId) -> Result<(), Box<dyn Error>> {
+        let entity_transform_index = self.entity_to_transform_buffer_index.remove(entity_id)
+            .ok_or(format!("entity {:?} has no transform bound", entity_id))?;
+        let last_transform_index = self.transform_buffer_index_to_entity.len() - 1;
+        if entity_transform_index != last_transform_index {
+            let moved_entity_id = self.transform_buffer_index_to_entity[last_transform_index];
+            self.entity_to_transform_buffer_index.insert(moved_entity_id, entity_transform_index);
+            self.mark_dirty(entity_transform_index);
+        }
+        self.transform_buffer_index_to_entity.swap_remove(entity_transform_index);
+        self.model_matrices.swap_remove(entity_transform_index);
         Ok(())
     }
 
-    pub fn copy_transform_data_slice_to_buffer(& self, entity_transforms_first_index: usize, entity_transforms_last_index: usize, entity_model_matrices: &[[[f32; 4]; 4]], next_swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
-        let mut write_lock =  self.transform_buffers[next_swapchain_image_index].write()?;
-        write_lock[entity_transforms_first_index..entity_transforms_last_index].copy_from_slice(entity_model_matrices);
-        //println!("Successfully copied entity transform: {:?} to transform buffer with index: {}", entity_transform.model_matrix(), next_swapchain_image_index);
-        Ok(())
+    // e.g. when a recreated swapchain has a different number of images. buffers of added frames start out with every transform dirty
+    pub fn set_frame_count(&mut self, frame_count: usize) {
+        self.transform_buffers.truncate(frame_count);
+        self.dirty_transform_indexes.truncate(frame_count);
+        while self.transform_buffers.len() < frame_count {
+            self.transform_buffers.push(Self::allocate_transform_buffer(self.memory_allocator.clone(), self.capacity));
+            let mut dirty_transform_indexes = DirtyTransformIndexes::default();
+            for transform_index in 0..self.model_matrices.len() {
+                dirty_transform_indexes.mark(transform_index);
+            }
+            self.dirty_transform_indexes.push(dirty_transform_indexes);
+        }
+    }
+
+    pub fn transform_count(&self) -> usize {
+        self.transform_buffer_index_to_entity.len()
     }
 
-    pub fn get_tansform_buffer_copy_payload(& mut self, unsynched_ahead_buffer_index: usize) -> Option<TransformBufferCopyPayload> {
-        let newly_added_transform_indexes = self.newly_added_transform_indexes.clone();
-        if newly_added_transform_indexes.len() < 1 {
-            return None
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn usage(&self) -> TransformBufferUsage {
+        TransformBufferUsage {
+            transform_count: self.transform_count(),
+            capacity: self.capacity,
+            allocated_bytes: self.transform_buffers.iter().map(|transform_buffer| transform_buffer.size()).sum()
         }
-        println!("Synching {} newly added transforms", newly_added_transform_indexes.len());
-        let most_up_to_date_buffer = &self.transform_buffers[unsynched_ahead_buffer_index];
-        let mut buffers_to_update = Vec::new();
-        println!("Source buffer index: {}", unsynched_ahead_buffer_index);
-        for (i, transform_buffer) in self.transform_buffers.iter().enumerate() {
-            if i != unsynched_ahead_buffer_index {
-                println!("Adding buffer with index: {} to TransformBufferCopyPayload", i);
-                buffers_to_update.push(transform_buffer.clone());
-            }
+    }
+
+    pub fn get_entity_transform_index(&self, entity_id: EntityId) -> Option<usize> {
+        self.entity_to_transform_buffer_index.get(entity_id).copied()
+    }
+
+    pub fn update_entity_transform(&mut self, entity_id: EntityId, entity_transform: &Transform) -> Result<(), Box<dyn Error>> {
+        let entity_transform_index = self.get_entity_transform_index(entity_id)
+            .ok_or(format!("entity {:?} has no transform bound", entity_id))?;
+        self.model_matrices[entity_transform_index] = entity_transform.model_matrix();
+        self.mark_dirty(entity_transform_index);
+        Ok(())
+    }
+
+    fn mark_dirty(&mut self, entity_transform_index: usize) {
+        for dirty_transform_indexes in self.dirty_transform_indexes.iter_mut() {
+            dirty_transform_indexes.mark(entity_transform_index);
         }
-        self.newly_added_transform_indexes.clear();
-        return Some(TransformBufferCopyPayload {
-            src_buffer: most_up_to_date_buffer.clone(), 
-            target_buffers: buffers_to_update, 
-            newly_added_transform_indexes
-        })
     }
 
-    pub fn clear_newly_added_transform_indexes(& mut self) -> () {
-        self.newly_added_transform_indexes.clear();
+    // writes every slot that changed since the last flush of this frame's buffer, one contiguous range at a time.
+    // a buffer that became too small is replaced by a bigger one holding all transforms, the descriptor sets pick it up since they get built per recorded frame.
+    // the frame must not be in use by the gpu anymore
+    pub fn flush_dirty_transforms(&mut self, swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
+        let mut dirty_ranges = self.dirty_transform_indexes[swapchain_image_index].take_ranges(self.model_matrices.len());
+        if (self.transform_buffers[swapchain_image_index].len() as usize) < self.capacity {
+            self.transform_buffers[swapchain_image_index] = Self::allocate_transform_buffer(self.memory_allocator.clone(), self.capacity);
+            dirty_ranges = vec![Range { start: 0, end: self.model_matrices.len() }];
+        }
+        if dirty_ranges.is_empty() {
+            return Ok(());
+        }
+        let mut write_lock = self.transform_buffers[swapchain_image_index].write()?;
+        for dirty_range in dirty_ranges {
+            write_lock[dirty_range.clone()].copy_from_slice(&self.model_matrices[dirty_range]);
+        }
+        Ok(())
     }
-    
 }
 
 impl Index<usize> for TransformBuffers {
@@ -114,4 +171,100 @@ impl Index<usize> for TransformBuffers {
     fn index(&self, index: usize) -> &Self::Output {
         &self.transform_buffers[index]
     }
-}
\ No newline at end of file
+}
+
+// marking a slot that is already dirty is a no op, so moving the same entities every frame doesn't pile up indexes between flushes
+#[derive(Debug, Default, Clone)]
+pub struct DirtyTransformIndexes {
+    // per transform slot, grows up to the highest marked slot
+    is_dirty: Vec<bool>,
+    // the dirty slots in the order they were first marked
+    indexes: Vec<usize>,
+}
+
+impl DirtyTransformIndexes {
+    pub fn mark(&mut self, transform_index: usize) {
+        if transform_index >= self.is_dirty.len() {
+            self.is_dirty.resize(transform_index + 1, false);
+        }
+        if !self.is_dirty[transform_index] {
+            self.is_dirty[transform_index] = true;
+            self.indexes.push(transform_index);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    // clears the marked indexes, indexes at or past transform_count (slots freed since they were marked) are dropped
+    pub fn take_ranges(&mut self, transform_count: usize) -> Vec<Range<usize>> {
+        let mut indexes = std::mem::take(&mut self.indexes);
+        for transform_index in indexes.iter() {
+            self.is_dirty[*transform_index] = false;
+        }
+        indexes.retain(|transform_index| *transform_index < transform_count);
+        coalesce_indexes(indexes)
+    }
+}
+
+// sorted, non overlapping and non adjacent ranges covering exactly the given indexes (e.g. dirty transform slots)
+pub fn coalesce_indexes(mut indexes: Vec<usize>) -> Vec<Range<usize>> {
+    indexes.sort_unstable();
+    indexes.dedup();
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for index in indexes {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacent_indexes_are_merged_into_one_range() {
+        assert_eq!(coalesce_indexes(vec![3, 4, 5, 6]), vec![3..7]);
+    }
+
+    #[test]
+    fn gaps_split_ranges() {
+        assert_eq!(coalesce_indexes(vec![0, 1, 5, 7, 8]), vec![0..2, 5..6, 7..9]);
+    }
+
+    #[test]
+    fn unsorted_and_duplicate_indexes_are_coalesced() {
+        assert_eq!(coalesce_indexes(vec![9, 2, 3, 2, 8, 3, 10]), vec![2..4, 8..11]);
+        assert!(coalesce_indexes(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn taking_ranges_drops_freed_slots_and_clears_the_indexes() {
+        let mut dirty_transform_indexes = DirtyTransformIndexes::default();
+        for transform_index in [1, 2, 6, 7] {
+            dirty_transform_indexes.mark(transform_index);
+        }
+        assert_eq!(dirty_transform_indexes.take_ranges(7), vec![1..3, 6..7]);
+        assert!(dirty_transform_indexes.is_empty());
+        assert!(dirty_transform_indexes.take_ranges(7).is_empty());
+    }
+
+    #[test]
+    fn slots_marked_again_before_a_flush_are_kept_once() {
+        let mut dirty_transform_indexes = DirtyTransformIndexes::default();
+        for _ in 0..1000 {
+            dirty_transform_indexes.mark(3);
+            dirty_transform_indexes.mark(0);
+        }
+        assert_eq!(dirty_transform_indexes.indexes, vec![3, 0]);
+        assert_eq!(dirty_transform_indexes.take_ranges(4), vec![0..1, 3..4]);
+
+        // taking the ranges resets the slots, so they can be marked again
+        dirty_transform_indexes.mark(3);
+        assert_eq!(dirty_transform_indexes.take_ranges(4), vec![3..4]);
+    }
+}
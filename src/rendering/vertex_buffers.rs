@@ -1,74 +1,289 @@
-use std::{collections::HashMap, error::Error, ops::Index, sync::Arc};
+use std::{error::Error, mem::size_of, sync::Arc};
 
-use egui_winit_vulkano::egui::epaint;
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}};
+use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::{AutoCommandBufferBuilder, BufferCopy, CopyBufferInfo, PrimaryAutoCommandBuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}, sync::Sharing};
 
-use super::{mesh_accessor::{MeshAccessor, MeshAccessorAddEntityResult}, primitives::{Mesh, Vertex}};
+use crate::engine::entity_id::EntityId;
 
+use super::{free_list_allocator::{grown_capacity, FreeListAllocator}, mesh_accessor::{MeshAccessor, MeshLocation}, primitives::{Mesh, Vertex}};
+
+// vertex and index arena: every loaded mesh owns one range in each buffer, freed ranges are reused and the buffers grow when full.
+// the buffers are device local, mesh data gets there through staging buffers that are copied with the next recorded frame (or transfer command buffer)
 pub struct VertexBuffer {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    // the graphics and, if there is one, the transfer queue family, both access the buffers
+    queue_family_indices: Vec<u32>,
     pub vertex_buffer: Subbuffer<[Vertex]>,
+    pub index_buffer: Subbuffer<[u32]>,
+    vertex_allocator: FreeListAllocator,
+    index_allocator: FreeListAllocator,
     pub mesh_accessor: MeshAccessor,
-    pub newly_added_mesh_first_and_last_vertex_index: Option<(usize, usize)>
+    // (first vertex/index, data) of everything written since the last upload
+    pending_vertex_uploads: Vec<(usize, Vec<Vertex>)>,
+    pending_index_uploads: Vec<(usize, Vec<u32>)>,
 }
-const INITIAL_VERTEX_BUFFER_SIZE: usize = 2_i32.pow(16) as usize; 
+// a staging buffer and the regions to copy out of it
+type StagingBuffer<T> = (Subbuffer<[T]>, Vec<BufferCopy>);
+
+const INITIAL_VERTEX_BUFFER_SIZE: usize = 2_i32.pow(16) as usize;
+const INITIAL_INDEX_BUFFER_SIZE: usize = 2_i32.pow(18) as usize;
 
 impl VertexBuffer {
-    pub fn new(memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
-        let initializer_data = vec![Vertex{position: [0.,0.,0.]}; INITIAL_VERTEX_BUFFER_SIZE];
-        let vertex_buffer = Buffer::from_iter(
-            memory_allocator.clone(),
+    pub fn new(memory_allocator: Arc<StandardMemoryAllocator>, queue_family_indices: Vec<u32>) -> Self {
+        let vertex_buffer = Self::allocate_buffer(memory_allocator.clone(), &queue_family_indices, BufferUsage::VERTEX_BUFFER, INITIAL_VERTEX_BUFFER_SIZE);
+        let index_buffer = Self::allocate_buffer(memory_allocator.clone(), &queue_family_indices, BufferUsage::INDEX_BUFFER, INITIAL_INDEX_BUFFER_SIZE);
+        let vertex_allocator = FreeListAllocator::new(INITIAL_VERTEX_BUFFER_SIZE);
+        let index_allocator = FreeListAllocator::new(INITIAL_INDEX_BUFFER_SIZE);
+        let mesh_accessor = MeshAccessor::default();
+
+        Self {
+            memory_allocator,
+            queue_family_indices,
+            vertex_buffer,
+            index_buffer,
+            vertex_allocator,
+            index_allocator,
+            mesh_accessor,
+            pending_vertex_uploads: Vec::new(),
+            pending_index_uploads: Vec::new(),
+        }
+    }
+
+    fn allocate_buffer<T: BufferContents>(memory_allocator: Arc<StandardMemoryAllocator>, queue_family_indices: &[u32], usage: BufferUsage, len: usize) -> Subbuffer<[T]> {
+        let sharing = match queue_family_indices.len() {
+            1 => Sharing::Exclusive,
+            _ => Sharing::Concurrent(queue_family_indices.iter().copied().collect()),
+        };
+        Buffer::new_slice(
+            memory_allocator,
             BufferCreateInfo {
-                usage: BufferUsage::VERTEX_BUFFER,
+                usage: usage | BufferUsage::TRANSFER_DST,
+                sharing,
                 ..Default::default()
             },
             AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
                 ..Default::default()
             },
-            initializer_data.into_iter()
+            len as u64
         )
-        .unwrap();
- 
-        let mesh_accessor = MeshAccessor::default();
+        .unwrap()
+    }
 
-        Self {
-            vertex_buffer,
-            mesh_accessor,
-            newly_added_mesh_first_and_last_vertex_index: None
+    pub fn bind_entity_mesh(&mut self, entity_mesh: &Mesh, entity_id: EntityId) -> Result<(), Box<dyn Error>> {
+        if !self.mesh_accessor.contains_mesh(entity_mesh.get_name()) {
+            self.load_mesh(entity_mesh.clone())?;
         }
+        self.mesh_accessor.add_entity(entity_mesh.get_name(), entity_id);
+        Ok(())
     }
 
-    pub fn bind_entity_mesh(&mut self, entity_mesh: Mesh, next_swapchain_image_index: usize) -> Result<(), Box<dyn Error>> {
-        let first_index = self.mesh_accessor.get_last_vertex_index();
-
-        let entity_add_result: MeshAccessorAddEntityResult = self.mesh_accessor.add_entity(entity_mesh);
-        match entity_add_result {
-            MeshAccessorAddEntityResult::AppendedToExistingMesh => {},
-            MeshAccessorAddEntityResult::CreatedNewMesh(mesh) => {
-                self.copy_blueprint_mesh_data_to_vertex_buffer(first_index, &mesh.data)?;
-                self.newly_added_mesh_first_and_last_vertex_index = Some((first_index, self.mesh_accessor.get_last_vertex_index()));
-            }
+    // the mesh data stays in the vertex buffer until unload_mesh, so spawning the same mesh again is cheap
+    pub fn unbind_entity_mesh(&mut self, entity_id: EntityId) -> Result<(), Box<dyn Error>> {
+        match self.mesh_accessor.remove_entity(entity_id) {
+            Some(_) => Ok(()),
+            None => Err(format!("entity {:?} has no mesh bound", entity_id).into())
         }
+    }
+
+    fn load_mesh(&mut self, mesh: Mesh) -> Result<(), Box<dyn Error>> {
+        let first_vertex_index = self.allocate_vertices(mesh.data.len());
+        let first_index = self.allocate_indices(mesh.indices.len());
+        self.queue_vertex_upload(first_vertex_index, &mesh.data);
+        self.queue_index_upload(first_index, &mesh.indices);
+        self.mesh_accessor.add_mesh(mesh, first_vertex_index, first_index);
         Ok(())
     }
 
-    fn copy_blueprint_mesh_data_to_vertex_buffer(& self, first_index: usize, mesh_data: &Vec<Vertex>) -> Result<(), Box<dyn Error>> {
-        println!("Copying new mesh data to vertex buffer");
-        println!("first vertex buffer index for mesh: {}", first_index);
-        println!("last vertex buffer index for mesh: {}", mesh_data.iter().len());
-        let mut write_lock = self.vertex_buffer.write()?;
-        write_lock[first_index..mesh_data.iter().len()].copy_from_slice(mesh_data.as_slice());
-        //println!("Successfully copied mesh data: {:?} to vertex buffer with index: {}", mesh_data.as_slice(), next_swapchain_image_index);
+    // frees the mesh's vertex and index ranges for other meshes, only possible once no entity uses the mesh anymore
+    pub fn unload_mesh(&mut self, mesh_name: &str) -> Result<MeshLocation, Box<dyn Error>> {
+        let mesh_location = self.mesh_accessor.remove_mesh(mesh_name)
+            .ok_or(format!("mesh {mesh_name} is not loaded or still has instances"))?;
+        self.vertex_allocator.free(mesh_location.first_vertex_index, mesh_location.vertex_count);
+        self.index_allocator.free(mesh_location.first_index, mesh_location.index_count);
+        Ok(mesh_location)
+    }
+
+    // e.g. after a level was unloaded
+    pub fn unload_unused_meshes(&mut self) -> Vec<MeshLocation> {
+        self.mesh_accessor.unused_mesh_names().iter()
+            .map(|mesh_name| self.unload_mesh(mesh_name).unwrap())
+            .collect()
+    }
+
+    fn allocate_vertices(&mut self, vertex_count: usize) -> usize {
+        if let Some(first_vertex_index) = self.vertex_allocator.allocate(vertex_count) {
+            return first_vertex_index;
+        }
+        let capacity = grown_capacity(self.vertex_allocator.capacity(), self.vertex_allocator.required_capacity(vertex_count));
+        self.vertex_allocator.grow(capacity);
+        self.rebuild_vertex_buffer();
+        self.vertex_allocator.allocate(vertex_count).unwrap()
+    }
+
+    fn allocate_indices(&mut self, index_count: usize) -> usize {
+        if let Some(first_index) = self.index_allocator.allocate(index_count) {
+            return first_index;
+        }
+        let capacity = grown_capacity(self.index_allocator.capacity(), self.index_allocator.required_capacity(index_count));
+        self.index_allocator.grow(capacity);
+        self.rebuild_index_buffer();
+        self.index_allocator.allocate(index_count).unwrap()
+    }
+
+    // the buffers are rebuilt from the meshes kept in the mesh accessor instead of being written in place,
+    // command buffers of frames still in flight keep the old buffers alive until they are done with them.
+    // uploads still pending for the old buffer are superseded by the full re-upload
+    fn rebuild_vertex_buffer(&mut self) {
+        self.vertex_buffer = Self::allocate_buffer(self.memory_allocator.clone(), &self.queue_family_indices, BufferUsage::VERTEX_BUFFER, self.vertex_allocator.capacity());
+        self.pending_vertex_uploads = self.mesh_accessor.meshes.iter()
+            .map(|mesh| (self.mesh_accessor.mesh_name_first_vertex_index_map[mesh.get_name()], mesh.data.clone()))
+            .collect();
+    }
+
+    fn rebuild_index_buffer(&mut self) {
+        self.index_buffer = Self::allocate_buffer(self.memory_allocator.clone(), &self.queue_family_indices, BufferUsage::INDEX_BUFFER, self.index_allocator.capacity());
+        self.pending_index_uploads = self.mesh_accessor.meshes.iter()
+            .map(|mesh| (self.mesh_accessor.mesh_name_first_index_map[mesh.get_name()], mesh.indices.clone()))
+            .collect();
+    }
+
+    // packs all loaded meshes to the start of the buffers, so the free space left by unloaded meshes becomes one range again
+    pub fn compact(&mut self) {
+        let compacted_locations = compacted_mesh_locations(
+            self.mesh_accessor.meshes.iter()
+                .map(|mesh| self.mesh_accessor.mesh_location(mesh.get_name()).unwrap())
+                .collect()
+        );
+        for (mesh_index, compacted_location) in compacted_locations.iter().enumerate() {
+            let mesh_name = self.mesh_accessor.meshes[mesh_index].get_name().clone();
+            self.mesh_accessor.set_mesh_location(&mesh_name, compacted_location.first_vertex_index, compacted_location.first_index);
+        }
+        self.vertex_allocator = FreeListAllocator::new(self.vertex_allocator.capacity());
+        self.vertex_allocator.allocate(compacted_locations.iter().map(|location| location.vertex_count).sum());
+        self.index_allocator = FreeListAllocator::new(self.index_allocator.capacity());
+        self.index_allocator.allocate(compacted_locations.iter().map(|location| location.index_count).sum());
+        self.rebuild_vertex_buffer();
+        self.rebuild_index_buffer();
+    }
+
+    // (used, capacity) in vertices and in indices
+    pub fn vertex_usage(&self) -> (usize, usize) {
+        (self.vertex_allocator.used(), self.vertex_allocator.capacity())
+    }
+
+    pub fn index_usage(&self) -> (usize, usize) {
+        (self.index_allocator.used(), self.index_allocator.capacity())
+    }
+
+    fn queue_vertex_upload(&mut self, first_vertex_index: usize, mesh_data: &[Vertex]) {
+        if !mesh_data.is_empty() {
+            self.pending_vertex_uploads.push((first_vertex_index, mesh_data.to_vec()));
+        }
+    }
+
+    // indices stay relative to the mesh, the draw call adds the mesh's first vertex index as vertex offset
+    fn queue_index_upload(&mut self, first_index: usize, mesh_indices: &[u32]) {
+        if !mesh_indices.is_empty() {
+            self.pending_index_uploads.push((first_index, mesh_indices.to_vec()));
+        }
+    }
+
+    pub fn has_pending_uploads(&self) -> bool {
+        !self.pending_vertex_uploads.is_empty() || !self.pending_index_uploads.is_empty()
+    }
+
+    // all pending uploads of a kind share one staging buffer and one copy command
+    pub fn record_pending_uploads(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> Result<(), Box<dyn Error>> {
+        let pending_vertex_uploads = std::mem::take(&mut self.pending_vertex_uploads);
+        if !pending_vertex_uploads.is_empty() {
+            let (staging_buffer, regions) = self.build_staging_buffer(pending_vertex_uploads)?;
+            builder.copy_buffer(CopyBufferInfo {
+                regions: regions.into(),
+                ..CopyBufferInfo::buffers(staging_buffer, self.vertex_buffer.clone())
+            })?;
+        }
+        let pending_index_uploads = std::mem::take(&mut self.pending_index_uploads);
+        if !pending_index_uploads.is_empty() {
+            let (staging_buffer, regions) = self.build_staging_buffer(pending_index_uploads)?;
+            builder.copy_buffer(CopyBufferInfo {
+                regions: regions.into(),
+                ..CopyBufferInfo::buffers(staging_buffer, self.index_buffer.clone())
+            })?;
+        }
         Ok(())
     }
 
-    //pub fn get_synch_info(&self, unsynched_ahead_buffer_index: usize) -> (Subbuffer<[Vertex]>, Vec<Subbuffer<[Vertex]>>) {
-    //    let most_up_to_date_buffer = &self.vertex_buffers[unsynched_ahead_buffer_index];
-    //    let mut buffers_to_update = Vec::new();
-    //    for (i, transform_buffer) in self.vertex_buffers.iter().enumerate() {
-    //        buffers_to_update.push(transform_buffer.clone());
-    //    }
-    //    return (most_up_to_date_buffer.clone(), buffers_to_update)
-    //}
-    
-}
\ No newline at end of file
+    fn build_staging_buffer<T: BufferContents + Copy>(&self, uploads: Vec<(usize, Vec<T>)>) -> Result<StagingBuffer<T>, Box<dyn Error>> {
+        let regions = staging_copy_regions(&uploads, size_of::<T>());
+        let staging_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            uploads.into_iter().flat_map(|(_, data)| data).collect::<Vec<T>>()
+        )?;
+        Ok((staging_buffer, regions))
+    }
+}
+
+// the uploads' data is laid out back to back in the staging buffer, each region copies one of them to its destination (offsets in bytes)
+pub fn staging_copy_regions<T>(uploads: &[(usize, Vec<T>)], element_size: usize) -> Vec<BufferCopy> {
+    let mut staging_offset = 0;
+    uploads.iter().map(|(destination_index, data)| {
+        let region = BufferCopy {
+            src_offset: (staging_offset * element_size) as u64,
+            dst_offset: (destination_index * element_size) as u64,
+            size: (data.len() * element_size) as u64,
+            ..Default::default()
+        };
+        staging_offset += data.len();
+        region
+    }).collect()
+}
+
+// the given locations packed back to back in the same order, vertices and indices independently
+pub fn compacted_mesh_locations(mesh_locations: Vec<MeshLocation>) -> Vec<MeshLocation> {
+    let mut next_vertex_index = 0;
+    let mut next_index = 0;
+    mesh_locations.into_iter().map(|mesh_location| {
+        let compacted_location = MeshLocation {
+            first_vertex_index: next_vertex_index,
+            first_index: next_index,
+            ..mesh_location
+        };
+        next_vertex_index += mesh_location.vertex_count;
+        next_index += mesh_location.index_count;
+        compacted_location
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compaction_packs_meshes_back_to_back() {
+        let mesh_locations = vec![
+            MeshLocation { first_vertex_index: 10, vertex_count: 4, first_index: 100, index_count: 6 },
+            MeshLocation { first_vertex_index: 2, vertex_count: 8, first_index: 0, index_count: 36 },
+        ];
+        assert_eq!(compacted_mesh_locations(mesh_locations), vec![
+            MeshLocation { first_vertex_index: 0, vertex_count: 4, first_index: 0, index_count: 6 },
+            MeshLocation { first_vertex_index: 4, vertex_count: 8, first_index: 6, index_count: 36 },
+        ]);
+    }
+
+    #[test]
+    fn staging_regions_read_back_to_back_and_write_to_their_destination() {
+        let uploads = vec![(10, vec![0u32; 3]), (2, vec![0u32; 5])];
+        let regions = staging_copy_regions(&uploads, 4);
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].src_offset, regions[0].dst_offset, regions[0].size), (0, 40, 12));
+        assert_eq!((regions[1].src_offset, regions[1].dst_offset, regions[1].size), (12, 8, 20));
+    }
+}